@@ -1,35 +1,52 @@
-/// Blinking LED demo
-///
-/// You need to run by `sudo` if you see the error below:
-///
-/// `Error: PermissionDenied("/dev/gpiomem")`
+//! Blinking LED demo
+//!
+//! Play each status LED pattern for 10 seconds in turn: plain 1s on/1s off blink, heartbeat,
+//! fast blink, error code 3 and "SOS" in Morse code. `Ctrl+C` turns the LED off and exits.
+//!
+//! You need to run by `sudo` if you see the error below:
+//!
+//! ```text
+//! Error: permission denied on `/dev/gpiomem`, run with `sudo`, ...
+//! ```
 
-use std::{ time::Duration };
-use rppal::gpio::{Result};
-use gpio_util::{GpioUtil, PinState};
+use std::{ process, thread, time::Duration };
+use gpio_util::{BlinkPattern, Morse, MorseTiming, PatternPlayer, PinRegistry, PinState, Result, SafeState};
 
 
 const GPIO_PIN_LED: u8 = 18;
 
-///
+/// Entry point, the pattern player blinks the LED in the background.
 fn main() -> Result<()>  {
-    let mut led = GpioUtil::create_output_pin(GPIO_PIN_LED).unwrap();
-    
-    // By default, pins are set to their original state when be dropped, 
+    let led = PinRegistry::global().output_pin(GPIO_PIN_LED, "status LED")?;
+
+    // By default, pins are set to their original state when be dropped,
     // you can use the method below to disable this feature:
     //
     // `InputPin::set_reset_on_drop(false)`
     // `OutputPin::set_reset_on_drop(false)`
     //
-    // For preventing `set_reset_on_drop` is being set, we better to reset to `LOW` right now.
-    GpioUtil::set_pin_state(&mut led, PinState::Low, Some(Duration::from_millis(100)));
-     
-    loop {
-        println!("---> LED is on");
-        GpioUtil::set_pin_state(&mut led, PinState::High, Some(Duration::from_secs(1)));
+    // `process::exit` doesn't drop anything though, so the LED is registered with its safe
+    // level (`LOW`, set right now) and the `Ctrl+C` handler and panic hook turn it off.
+    let safe_state = SafeState::global();
+    safe_state.install_panic_hook();
+    safe_state.install_signal_handler(|| process::exit(130))?;
+    let led = safe_state.register(led, PinState::Low, "status LED");
 
-        println!("---> LED is off");
-        GpioUtil::set_pin_state(&mut led, PinState::Low, Some(Duration::from_secs(1)));
+    let status_led = PatternPlayer::new(led);
+    let patterns = [
+        ("blink", BlinkPattern::blink(Duration::from_secs(1), Duration::from_secs(1))),
+        ("heartbeat", BlinkPattern::heartbeat()),
+        ("fast blink", BlinkPattern::fast_blink()),
+        ("error code 3", BlinkPattern::error_code(3)),
+        ("morse SOS", Morse::encode("SOS", &MorseTiming::new(15)?)),
+    ];
+
+    loop {
+        for (name, pattern) in patterns.iter() {
+            println!("---> LED pattern: {}", name);
+            status_led.play(pattern.clone());
+            thread::sleep(Duration::from_secs(10));
+        }
     }
 
     // Ok(())
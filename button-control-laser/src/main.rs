@@ -1,44 +1,59 @@
-/// Use button to control laser
-///
-/// How to connect the circuit: 
-///
-/// Pi               Button   Laser Emitter
-/// ====             ======   =============
-/// 5V     ----------  VCC 
-/// GND    ----------  GND 
-/// GPIO17 ----------  SIG
-///
-/// GPIO27 -----------------  SIG
-/// 5V     -----------------  VCC 
-/// GND    -----------------  GND 
-///
-///
-/// You need to run by `sudo` if you see the error below:
-///
-/// `Error: PermissionDenied("/dev/gpiomem")`
-///
-
-use std::{thread, time::Duration};
-use rppal::gpio::{Result};
-use gpio_util::{GpioUtil, PinState};
-
-
-const GPIO_BUTTON_SIGNAL_PIN: u8 = 17;
-const GPIO_LASER_SIGNAL_PIN: u8 = 27;
-
-///
+//! Use button to control laser
+//!
+//! How to connect the circuit: 
+//!
+//! Pi                     Button   Laser Emitter
+//! ====                   ======   =============
+//! 5V              -----  VCC
+//! GND             -----  GND
+//! GPIO17 (pin 11) -----  SIG
+//!
+//! GPIO27 (pin 13) --------------  SIG
+//! 5V              --------------  VCC
+//! GND             --------------  GND
+//!
+//! `Ctrl+C` turns the laser off and exits.
+//!
+//! You need to run by `sudo` if you see the error below:
+//!
+//! ```text
+//! Error: permission denied on `/dev/gpiomem`, run with `sudo`, ...
+//! ```
+//!
+
+use std::{process, thread, time::Duration};
+use gpio_util::{DebounceConfig, Debouncer, GpioUtil, InputConfig, PinId, PinState, Result, SafeState};
+
+
+// Physical header pins, same with the wiring table above
+const BUTTON_SIGNAL_HEADER_PIN: u8 = 11;
+const LASER_SIGNAL_HEADER_PIN: u8 = 13;
+
+/// Entry point, every button press toggles the laser.
 fn main() -> Result<()>  {
-    let button_signal = GpioUtil::create_input_pin(GPIO_BUTTON_SIGNAL_PIN).unwrap();
-    let mut laser_signal = GpioUtil::create_output_pin(GPIO_LASER_SIGNAL_PIN).unwrap();
-    
-    // For preventing `set_reset_on_drop` is being set, we better to reset to `Low` right now.
-    GpioUtil::set_pin_state(&mut laser_signal, PinState::Low, Some(Duration::from_millis(100)));
+    // Fails right away if a header pin isn't a GPIO (e.g. wired to GND by mistake)
+    let button_pin = PinId::physical(BUTTON_SIGNAL_HEADER_PIN)?;
+    let laser_pin = PinId::physical(LASER_SIGNAL_HEADER_PIN)?;
+    println!("button: {}, laser: {}", button_pin, laser_pin);
+
+    // Internal pull-up, so a bare switch to GND works as well as the button module
+    let mut button_signal = GpioUtil::create_configured_input_pin(button_pin.bcm_number(), InputConfig::switch_to_ground())?;
+
+    // Safe level is `LOW` (set right now), `Ctrl+C` or a panic switches the laser off
+    let safe_state = SafeState::global();
+    safe_state.install_panic_hook();
+    safe_state.install_signal_handler(|| process::exit(130))?;
+    let mut laser_signal = safe_state.register(GpioUtil::create_output_pin(laser_pin.bcm_number())?, PinState::Low, "laser");
      
     let mut laser_is_on = false;
     let loop_duration = Duration::from_millis(100);
 
+    // Button contacts bounce, without debouncing one tap can toggle the laser twice.
+    let mut debouncer = Debouncer::new(DebounceConfig::default());
+
     loop {
-        GpioUtil::block_until_button_pressed(&button_signal);
+        // Sleep on the button interrupt instead of polling while nobody touches it
+        debouncer.wait_for_press_on_edges(&mut button_signal)?;
 
         laser_is_on = !laser_is_on;
         println!("laser_is_on: {}", laser_is_on);
@@ -0,0 +1,100 @@
+// Software PWM jitter vs CPU usage for every `PwmTiming`.
+//
+// `cargo bench --bench soft_pwm` runs a 1kHz 25% duty `SoftPwm` on a pin
+// which records when it goes `HIGH`, then prints how far each period is from
+// 1ms and how much CPU the process used meanwhile. No GPIO is touched, the
+// timing is the same as on a real pin minus the register write (~100ns).
+
+use gpio_util::{ DigitalOutput, PwmTiming, SoftPwm };
+use std::{
+    fs,
+    sync::{ Arc, Mutex },
+    thread,
+    time::{ Duration, Instant },
+};
+
+const FREQUENCY: f64 = 1_000.0;
+const DUTY_CYCLE: f64 = 0.25;
+const RUN_FOR: Duration = Duration::from_secs(3);
+
+// Remembers every rising edge.
+#[derive(Clone, Default)]
+struct RecordingPin {
+    rising_edges: Arc<Mutex<Vec<Instant>>>,
+    high: bool,
+}
+
+impl DigitalOutput for RecordingPin {
+    fn set_high(&mut self) {
+        if !self.high {
+            self.rising_edges.lock().unwrap().push(Instant::now());
+        }
+        self.high = true;
+    }
+
+    fn set_low(&mut self) {
+        self.high = false;
+    }
+
+    fn is_set_high(&self) -> bool {
+        self.high
+    }
+}
+
+// User + system time of this process, from `/proc/self/stat` (Linux only).
+fn cpu_time() -> Duration {
+    let stat = fs::read_to_string("/proc/self/stat").unwrap_or_default();
+    // Fields after the `(comm)`, which may contain spaces
+    let fields: Vec<&str> = stat.rsplit(')').next().unwrap_or("").split_whitespace().collect();
+    let ticks: u64 = fields.get(11..13).map_or(0, |times| times.iter().filter_map(|time| time.parse::<u64>().ok()).sum());
+    // `CLK_TCK` is 100 on every Linux we care about
+    Duration::from_millis(ticks * 10)
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+fn bench(name: &str, timing: PwmTiming) {
+    let pin = RecordingPin::default();
+    let rising_edges = pin.rising_edges.clone();
+
+    let cpu_before = cpu_time();
+    let started = Instant::now();
+    let pwm = SoftPwm::with_timing(pin, FREQUENCY, DUTY_CYCLE, timing).expect("valid PWM settings");
+    thread::sleep(RUN_FOR);
+    drop(pwm);
+    let wall = started.elapsed();
+    let cpu = cpu_time() - cpu_before;
+
+    let period = Duration::from_secs_f64(1.0 / FREQUENCY);
+    let edges = rising_edges.lock().unwrap();
+    let mut errors: Vec<f64> = edges
+        .windows(2)
+        .map(|pair| (micros(pair[1] - pair[0]) - micros(period)).abs())
+        .collect();
+    errors.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let average = errors.iter().sum::<f64>() / errors.len().max(1) as f64;
+    let p99 = errors.get(errors.len() * 99 / 100).copied().unwrap_or(0.0);
+    let max = errors.last().copied().unwrap_or(0.0);
+    let cpu_percent = cpu.as_secs_f64() / wall.as_secs_f64() * 100.0;
+
+    println!(
+        "{:<16} periods: {:>5}  error avg: {:>7.1}µs  p99: {:>7.1}µs  max: {:>7.1}µs  CPU: {:>5.1}%",
+        name,
+        errors.len(),
+        average,
+        p99,
+        max,
+        cpu_percent
+    );
+}
+
+fn main() {
+    println!("Software PWM, {}Hz {}% duty, {}s per timing\n", FREQUENCY, DUTY_CYCLE * 100.0, RUN_FOR.as_secs());
+    bench("Sleep", PwmTiming::Sleep);
+    bench("Hybrid (50µs)", PwmTiming::Hybrid { spin_margin: Duration::from_micros(50) });
+    bench("Hybrid (200µs)", PwmTiming::Hybrid { spin_margin: Duration::from_micros(200) });
+    bench("Spin", PwmTiming::Spin);
+}
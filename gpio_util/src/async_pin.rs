@@ -0,0 +1,240 @@
+// Async API, only with the `async` feature:
+//
+// ```toml
+// gpio_util={ path="../gpio_util", version="0.1.0", features=["async"] }
+// ```
+//
+// Built on `EdgeInput`, so the task sleeps until the pin's edge thread (the
+// `rppal` interrupt thread, or the `PollingInput` poller) wakes it, no
+// executor thread is blocked waiting. It only uses `futures` and
+// `futures-timer`, so it works on `tokio` as well as any other executor.
+//
+// Dropping one of the futures before it resolves (e.g. the losing branch of a
+// `select!`, or a timeout) unsubscribes the pin.
+//
+// ```rust
+// let mut button = GpioUtil::create_input_pin(17)?;
+//
+// let level = gpio_util::wait_for_edge(&mut button, Edge::Falling).await?;
+// gpio_util::wait_for_press(&mut button, DebounceConfig::default()).await?;
+//
+// let mut edges = gpio_util::edge_stream(&mut button, Edge::Both)?;
+// while let Some(level) = edges.next().await { ... }
+// ```
+
+use futures::{
+    channel::{ mpsc, oneshot },
+    StreamExt,
+};
+use futures_timer::Delay;
+use rppal::gpio::Level;
+use std::mem;
+use crate::{
+    clock::{ Clock, SystemClock },
+    debounce::{ ButtonEdge, DebounceConfig, Debouncer },
+    edge::{ Edge, EdgeInput },
+    error::{ GpioError, Result },
+};
+
+/// `Stream` of the new levels on every matching edge.
+pub type EdgeStream = mpsc::UnboundedReceiver<Level>;
+
+/// Subscribe to `pin` and return its edges as a `Stream`. The stream ends
+/// when the pin unsubscribes or is dropped.
+pub fn edge_stream<P: EdgeInput>(pin: &mut P, edge: Edge) -> Result<EdgeStream> {
+    let (sender, receiver) = mpsc::unbounded();
+    pin.subscribe(edge, move |level| { let _ = sender.unbounded_send(level); })?;
+    Ok(receiver)
+}
+
+// Unsubscribes the pin when dropped, so a future dropped halfway doesn't
+// leave the callback installed.
+struct Subscription<'a, P: EdgeInput> {
+    pin: &'a mut P,
+}
+
+impl<'a, P: EdgeInput> Subscription<'a, P> {
+    // Unsubscribe now, with the error if it fails.
+    fn end(self) -> Result<()> {
+        let result = self.pin.unsubscribe();
+        mem::forget(self);
+        result
+    }
+}
+
+impl<'a, P: EdgeInput> Drop for Subscription<'a, P> {
+    fn drop(&mut self) {
+        let _ = self.pin.unsubscribe();
+    }
+}
+
+/// Wait for the next matching edge and return the new level. Fail with
+/// `GpioError::SubscriptionEnded` if the edges stop coming first.
+pub async fn wait_for_edge<P: EdgeInput>(pin: &mut P, edge: Edge) -> Result<Level> {
+    let (sender, receiver) = oneshot::channel();
+    let mut sender = Some(sender);
+    pin.subscribe(edge, move |level| {
+        if let Some(sender) = sender.take() {
+            let _ = sender.send(level);
+        }
+    })?;
+    let subscription = Subscription { pin };
+
+    // The sender only goes away without sending if the subscription was dropped
+    let level = match receiver.await {
+        Ok(level) => level,
+        Err(_) => return Err(GpioError::SubscriptionEnded),
+    };
+    subscription.end()?;
+    Ok(level)
+}
+
+/// Wait for a debounced press and release, see `Debouncer::wait_for_press`.
+/// The task only wakes up on edges, and every `sample_period` while the
+/// input is settling. Fail with `GpioError::SubscriptionEnded` if the edges
+/// stop coming before the press is done.
+pub async fn wait_for_press<P: EdgeInput>(pin: &mut P, config: DebounceConfig) -> Result<()> {
+    let mut edges = edge_stream(pin, Edge::Both)?;
+    let subscription = Subscription { pin };
+    let clock = SystemClock::new();
+    let mut debouncer = Debouncer::new(config);
+    let mut pressed = false;
+
+    loop {
+        match debouncer.poll(&*subscription.pin, &clock) {
+            Some(ButtonEdge::Pressed) => pressed = true,
+            Some(ButtonEdge::Released) if pressed => break,
+            _ => {}
+        }
+
+        if debouncer.is_settling() {
+            Delay::new(config.sample_period).await;
+        } else if edges.next().await.is_none() {
+            return Err(GpioError::SubscriptionEnded);
+        }
+    }
+
+    subscription.end()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{ DigitalInput, MemoryPin, PollingInput };
+    use futures::{ executor::block_on, FutureExt };
+    use std::{ thread, time::Duration };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Never has an edge, and keeps the callback only if `keep_callback`.
+    #[derive(Default)]
+    struct SilentInput {
+        keep_callback: bool,
+        callback: Option<Box<dyn FnMut(Level) + Send>>,
+        subscribed: bool,
+    }
+
+    impl DigitalInput for SilentInput {
+        fn read(&self) -> Level {
+            Level::High
+        }
+    }
+
+    impl EdgeInput for SilentInput {
+        fn subscribe<F>(&mut self, _edge: Edge, callback: F) -> Result<()>
+        where
+            F: FnMut(Level) + Send + 'static,
+        {
+            if self.keep_callback {
+                self.callback = Some(Box::new(callback));
+            }
+            self.subscribed = true;
+            Ok(())
+        }
+
+        fn unsubscribe(&mut self) -> Result<()> {
+            self.callback = None;
+            self.subscribed = false;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn wait_for_edge_should_resolve_on_the_edge() {
+        let button = MemoryPin::new(Level::High);
+        let finger = button.clone();
+        let mut input = PollingInput::new(button, ms(1));
+
+        let presser = thread::spawn(move || {
+            thread::sleep(ms(50));
+            finger.set_level(Level::Low);
+        });
+
+        assert_eq!(block_on(wait_for_edge(&mut input, Edge::Falling)).unwrap(), Level::Low);
+        presser.join().unwrap();
+    }
+
+    #[test]
+    fn wait_for_press_should_resolve_after_release() {
+        let button = MemoryPin::new(Level::High);
+        let finger = button.clone();
+        let mut input = PollingInput::new(button, ms(1));
+
+        let presser = thread::spawn(move || {
+            thread::sleep(ms(50));
+            finger.set_level(Level::Low);
+            thread::sleep(ms(100));
+            finger.set_level(Level::High);
+        });
+
+        block_on(wait_for_press(&mut input, DebounceConfig::default())).unwrap();
+        assert_eq!(input.read(), Level::High);
+        presser.join().unwrap();
+    }
+
+    #[test]
+    fn dropped_or_ended_waits_should_unsubscribe() {
+        // Dropped before any edge, e.g. the losing `select!` branch
+        let mut input = SilentInput { keep_callback: true, ..Default::default() };
+        assert!(wait_for_edge(&mut input, Edge::Falling).now_or_never().is_none());
+        assert!(!input.subscribed && input.callback.is_none());
+
+        // The callback is dropped without an edge, that's no edge
+        let mut input = SilentInput::default();
+        match block_on(wait_for_edge(&mut input, Edge::Falling)) {
+            Err(GpioError::SubscriptionEnded) => {}
+            other => panic!("expected `SubscriptionEnded`, got {:?}", other),
+        }
+        assert!(!input.subscribed);
+
+        // The edges stop coming, that's no press
+        let mut input = SilentInput::default();
+        match block_on(wait_for_press(&mut input, DebounceConfig::default())) {
+            Err(GpioError::SubscriptionEnded) => {}
+            other => panic!("expected `SubscriptionEnded`, got {:?}", other),
+        }
+        assert!(!input.subscribed);
+    }
+
+    #[test]
+    fn edge_stream_should_yield_every_edge() {
+        let button = MemoryPin::new(Level::High);
+        let finger = button.clone();
+        let mut input = PollingInput::new(button, ms(1));
+        let edges = edge_stream(&mut input, Edge::Both).unwrap();
+
+        let presser = thread::spawn(move || {
+            for level in [Level::Low, Level::High, Level::Low].iter() {
+                thread::sleep(ms(20));
+                finger.set_level(*level);
+            }
+        });
+
+        let levels: Vec<Level> = block_on(edges.take(3).collect());
+        assert_eq!(levels, vec![Level::Low, Level::High, Level::Low]);
+        presser.join().unwrap();
+    }
+}
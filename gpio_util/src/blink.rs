@@ -0,0 +1,220 @@
+// Blink patterns for status LEDs.
+//
+// A `BlinkPattern` is a list of `(state, duration)` steps plus how many
+// times to repeat it. `PatternPlayer` owns the pin and plays the pattern on
+// its own thread, `play` switches to another pattern right away:
+//
+// ```rust
+// let status_led = PatternPlayer::new(GpioUtil::create_output_pin(18)?);
+// status_led.play(BlinkPattern::heartbeat());
+//
+// // Something went wrong, blink error code 3
+// status_led.play(BlinkPattern::error_code(3));
+// ```
+
+use std::{
+    sync::mpsc::{ channel, Receiver, RecvTimeoutError, Sender },
+    thread,
+    time::Duration,
+};
+use crate::{ digital::DigitalOutput, PinState };
+
+/// How many times a pattern runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Times(u32),
+    Forever,
+}
+
+/// Sequence of output states with their durations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlinkPattern {
+    pub steps: Vec<(PinState, Duration)>,
+    pub repeat: Repeat,
+}
+
+impl BlinkPattern {
+    /// Create a pattern from its steps.
+    pub fn new(steps: Vec<(PinState, Duration)>, repeat: Repeat) -> Self {
+        BlinkPattern { steps, repeat }
+    }
+
+    /// Stay at `state`.
+    pub fn steady(state: PinState) -> Self {
+        BlinkPattern::new(vec![(state, Duration::from_millis(0))], Repeat::Times(1))
+    }
+
+    /// On for `on`, off for `off`, forever.
+    pub fn blink(on: Duration, off: Duration) -> Self {
+        BlinkPattern::new(vec![(PinState::High, on), (PinState::Low, off)], Repeat::Forever)
+    }
+
+    /// Double flash once a second, "alive and well".
+    pub fn heartbeat() -> Self {
+        BlinkPattern::new(vec![
+            (PinState::High, Duration::from_millis(100)),
+            (PinState::Low, Duration::from_millis(100)),
+            (PinState::High, Duration::from_millis(100)),
+            (PinState::Low, Duration::from_millis(700)),
+        ], Repeat::Forever)
+    }
+
+    /// 5Hz blink, "something is wrong".
+    pub fn fast_blink() -> Self {
+        BlinkPattern::blink(Duration::from_millis(100), Duration::from_millis(100))
+    }
+
+    /// `code` blinks followed by a 1.5s pause, forever.
+    pub fn error_code(code: u32) -> Self {
+        let mut steps = Vec::new();
+        for _ in 0..code {
+            steps.push((PinState::High, Duration::from_millis(250)));
+            steps.push((PinState::Low, Duration::from_millis(250)));
+        }
+        steps.push((PinState::Low, Duration::from_millis(1500)));
+
+        BlinkPattern::new(steps, Repeat::Forever)
+    }
+}
+
+enum Command {
+    Play(BlinkPattern),
+    Stop,
+    Shutdown,
+}
+
+/// Plays `BlinkPattern`s on an output pin from a background thread.
+pub struct PatternPlayer {
+    commands: Sender<Command>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl PatternPlayer {
+    /// Take over `pin`, nothing plays until `play` is called.
+    pub fn new<P: DigitalOutput + Send + 'static>(pin: P) -> Self {
+        let (commands, command_receiver) = channel();
+        let worker = thread::spawn(move || {
+            let mut pin = pin;
+            let mut next_command = None;
+
+            loop {
+                let command = match next_command.take() {
+                    Some(command) => command,
+                    None => command_receiver.recv().unwrap_or(Command::Shutdown),
+                };
+
+                match command {
+                    Command::Play(pattern) => next_command = PatternPlayer::play_pattern(&mut pin, &pattern, &command_receiver),
+                    Command::Stop => pin.set_low(),
+                    Command::Shutdown => {
+                        pin.set_low();
+                        return;
+                    }
+                }
+            }
+        });
+
+        PatternPlayer { commands, worker: Some(worker) }
+    }
+
+    // Play `pattern` until it's done, or return the command which interrupted it.
+    fn play_pattern<P: DigitalOutput>(pin: &mut P, pattern: &BlinkPattern, commands: &Receiver<Command>) -> Option<Command> {
+        let rounds = match pattern.repeat {
+            Repeat::Times(times) => Some(times),
+            Repeat::Forever => None,
+        };
+
+        let mut round = 0;
+        while !pattern.steps.is_empty() && rounds.map_or(true, |times| round < times) {
+            for (state, duration) in pattern.steps.iter() {
+                match state {
+                    PinState::Low => pin.set_low(),
+                    PinState::High => pin.set_high(),
+                }
+
+                match commands.recv_timeout(*duration) {
+                    Ok(command) => return Some(command),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return Some(Command::Shutdown),
+                }
+            }
+            round += 1;
+        }
+
+        None
+    }
+
+    /// Switch to `pattern`, the current one stops right away.
+    pub fn play(&self, pattern: BlinkPattern) {
+        let _ = self.commands.send(Command::Play(pattern));
+    }
+
+    /// Stop playing and turn the pin `LOW`.
+    pub fn stop(&self) {
+        let _ = self.commands.send(Command::Stop);
+    }
+}
+
+impl Drop for PatternPlayer {
+    /// Stop the thread, the pin ends `LOW`.
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::MockGpio;
+    use rppal::gpio::Level;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn levels_of(gpio: &MockGpio, pin: u8) -> Vec<Level> {
+        gpio.transitions(pin).iter().map(|transition| transition.level).collect()
+    }
+
+    #[test]
+    fn pattern_should_play_the_given_number_of_times() {
+        let gpio = MockGpio::new();
+        let player = PatternPlayer::new(gpio.output_pin(18, Level::Low));
+
+        player.play(BlinkPattern::new(vec![(PinState::High, ms(5)), (PinState::Low, ms(5))], Repeat::Times(3)));
+        thread::sleep(ms(100));
+
+        assert_eq!(levels_of(&gpio, 18), vec![
+            Level::High, Level::Low,
+            Level::High, Level::Low,
+            Level::High, Level::Low,
+        ]);
+    }
+
+    #[test]
+    fn play_should_switch_patterns_at_runtime() {
+        let gpio = MockGpio::new();
+        let player = PatternPlayer::new(gpio.output_pin(18, Level::Low));
+
+        player.play(BlinkPattern::blink(ms(1000), ms(1000)));
+        thread::sleep(ms(20));
+        player.play(BlinkPattern::steady(PinState::Low));
+        thread::sleep(ms(20));
+
+        // Didn't wait for the 1s step to finish
+        assert_eq!(levels_of(&gpio, 18), vec![Level::High, Level::Low]);
+    }
+
+    #[test]
+    fn error_code_should_blink_the_code() {
+        let pattern = BlinkPattern::error_code(3);
+        let blinks = pattern.steps.iter().filter(|(state, _)| *state == PinState::High).count();
+
+        assert_eq!(blinks, 3);
+        assert_eq!(pattern.repeat, Repeat::Forever);
+    }
+}
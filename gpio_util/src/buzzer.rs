@@ -0,0 +1,421 @@
+// Tones and melodies for passive buzzers.
+//
+// An active buzzer beeps when its pin is `HIGH` (or `LOW`), a passive one
+// needs a square wave at the tone's frequency. Anything implementing
+// `ToneOutput` can play it: hardware `Pwm` (GPIO18/GPIO19) or `SoftPwm` on
+// any other pin. `TonePlayer` owns the output and plays `Melody`s on its own
+// thread, like `PatternPlayer` does for LEDs:
+//
+// ```rust
+// let buzzer = TonePlayer::new(Pwm::new(Channel::Pwm1)?);
+// buzzer.play(Melody::armed());
+//
+// // Somebody walked through the laser
+// buzzer.play(Melody::triggered());
+//
+// // RTTTL ringtones work too
+// buzzer.play(Melody::from_rtttl("beep:d=8,o=6,b=140:c,e,g,2c7")?);
+// ```
+//
+// A bad ringtone isn't a GPIO problem, so `from_rtttl` fails with its own
+// `MelodyError` instead of a `GpioError`.
+
+use rppal::pwm::Pwm;
+use std::{
+    error, fmt,
+    sync::{
+        mpsc::{ channel, Receiver, RecvTimeoutError, Sender },
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use crate::{
+    blink::Repeat,
+    error::{ GpioError, Result },
+    soft_pwm::SoftPwm,
+};
+
+// Semitones above C for `c d e f g a b`.
+const SEMITONES: [(char, i32); 8] = [('c', 0), ('d', 2), ('e', 4), ('f', 5), ('g', 7), ('a', 9), ('b', 11), ('h', 11)];
+
+// Notes sound for this part of their length, so repeated notes don't blur.
+const ARTICULATION: f64 = 0.9;
+
+/// Something which can produce a square wave, e.g. a passive buzzer on PWM.
+pub trait ToneOutput {
+    /// Start (or change) the tone.
+    fn tone(&mut self, frequency: f64) -> Result<()>;
+
+    /// Stop the tone.
+    fn silence(&mut self) -> Result<()>;
+}
+
+impl ToneOutput for Pwm {
+    fn tone(&mut self, frequency: f64) -> Result<()> {
+        self.set_frequency(frequency, 0.5)?;
+        self.enable()?;
+        Ok(())
+    }
+
+    fn silence(&mut self) -> Result<()> {
+        self.disable()?;
+        Ok(())
+    }
+}
+
+impl ToneOutput for SoftPwm {
+    fn tone(&mut self, frequency: f64) -> Result<()> {
+        self.set_frequency(frequency)?;
+        self.set_duty_cycle(0.5)
+    }
+
+    fn silence(&mut self) -> Result<()> {
+        self.set_duty_cycle(0.0)
+    }
+}
+
+/// The RTTTL text given to `Melody::from_rtttl` can't be parsed.
+#[derive(Clone, PartialEq)]
+pub struct MelodyError(String);
+
+impl fmt::Display for MelodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid melody: {}", self.0)
+    }
+}
+
+impl fmt::Debug for MelodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl error::Error for MelodyError {}
+
+/// One note, `frequency` is `None` for a rest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub frequency: Option<f64>,
+    pub duration: Duration,
+}
+
+impl Note {
+    /// A tone of `frequency` Hz.
+    pub fn tone(frequency: f64, duration: Duration) -> Self {
+        Note { frequency: Some(frequency), duration }
+    }
+
+    /// Silence.
+    pub fn rest(duration: Duration) -> Self {
+        Note { frequency: None, duration }
+    }
+}
+
+/// Sequence of notes plus how many times to play it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Melody {
+    pub notes: Vec<Note>,
+    pub repeat: Repeat,
+}
+
+impl Melody {
+    /// Create a melody from its notes.
+    pub fn new(notes: Vec<Note>, repeat: Repeat) -> Self {
+        Melody { notes, repeat }
+    }
+
+    /// Two rising chirps, once: "system armed".
+    pub fn armed() -> Self {
+        Melody::new(vec![
+            Note::tone(1_500.0, Duration::from_millis(80)),
+            Note::rest(Duration::from_millis(60)),
+            Note::tone(2_000.0, Duration::from_millis(120)),
+        ], Repeat::Times(1))
+    }
+
+    /// Hi-lo wail, forever: "intruder".
+    pub fn triggered() -> Self {
+        Melody::new(vec![
+            Note::tone(960.0, Duration::from_millis(400)),
+            Note::tone(770.0, Duration::from_millis(400)),
+        ], Repeat::Forever)
+    }
+
+    /// Three low beeps and a pause, forever: "sensor or wiring fault".
+    pub fn fault() -> Self {
+        let beep = Note::tone(400.0, Duration::from_millis(150));
+        let gap = Note::rest(Duration::from_millis(150));
+        Melody::new(vec![beep, gap, beep, gap, beep, Note::rest(Duration::from_millis(1500))], Repeat::Forever)
+    }
+
+    /// Parse a Nokia RTTTL ringtone, e.g. `"name:d=4,o=5,b=120:8a,c6,p,2e."`,
+    /// played once.
+    pub fn from_rtttl(text: &str) -> std::result::Result<Self, MelodyError> {
+        let invalid = |message: String| MelodyError(message);
+
+        let mut sections = text.splitn(3, ':');
+        let (_name, defaults, notes) = match (sections.next(), sections.next(), sections.next()) {
+            (Some(name), Some(defaults), Some(notes)) => (name, defaults, notes),
+            _ => return Err(invalid("RTTTL needs `name:defaults:notes`".to_string())),
+        };
+
+        // Defaults of the RTTTL spec
+        let (mut duration, mut octave, mut bpm) = (4u32, 6u32, 63u32);
+        for setting in defaults.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+            let mut key_value = setting.splitn(2, '=');
+            let key = key_value.next().unwrap_or("").trim();
+            let value = key_value.next().and_then(|value| value.trim().parse::<u32>().ok());
+            match (key, value) {
+                ("d", Some(value)) => duration = value,
+                ("o", Some(value)) => octave = value,
+                ("b", Some(value)) => bpm = value,
+                _ => return Err(invalid(format!("bad RTTTL default `{}`", setting))),
+            }
+        }
+        if bpm == 0 {
+            return Err(invalid("RTTTL tempo `b` can't be 0".to_string()));
+        }
+
+        // A whole note is 4 beats
+        let whole_note_ms = 60_000.0 * 4.0 / bpm as f64;
+        let notes = notes
+            .split(',')
+            .map(str::trim)
+            .filter(|note| !note.is_empty())
+            .map(|note| parse_rtttl_note(note, duration, octave, whole_note_ms).ok_or_else(|| invalid(format!("bad RTTTL note `{}`", note))))
+            .collect::<std::result::Result<Vec<Note>, MelodyError>>()?;
+
+        Ok(Melody::new(notes, Repeat::Times(1)))
+    }
+}
+
+// `[duration]note[#][.][octave][.]`
+fn parse_rtttl_note(note: &str, default_duration: u32, default_octave: u32, whole_note_ms: f64) -> Option<Note> {
+    let note = note.to_ascii_lowercase();
+    let mut chars = note.chars().peekable();
+
+    let mut digits = String::new();
+    while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*digit);
+        chars.next();
+    }
+    let duration = if digits.is_empty() { default_duration } else { digits.parse().ok()? };
+    if ![1, 2, 4, 8, 16, 32].contains(&duration) {
+        return None;
+    }
+
+    let name = chars.next()?;
+    let mut semitone = match name {
+        'p' => None,
+        _ => Some(SEMITONES.iter().find(|(letter, _)| *letter == name)?.1),
+    };
+    if chars.peek() == Some(&'#') {
+        chars.next();
+        semitone = semitone.map(|semitone| semitone + 1);
+    }
+
+    let mut dotted = false;
+    let mut octave = default_octave;
+    for c in chars {
+        match c {
+            '.' => dotted = true,
+            '4'..='7' => octave = c.to_digit(10)?,
+            _ => return None,
+        }
+    }
+
+    let mut length_ms = whole_note_ms / duration as f64;
+    if dotted {
+        length_ms *= 1.5;
+    }
+    let length = Duration::from_secs_f64(length_ms / 1000.0);
+
+    Some(match semitone {
+        // A4 is 440Hz, 12 semitones per octave
+        Some(semitone) => Note::tone(440.0 * 2f64.powf((semitone - 9) as f64 / 12.0 + octave as f64 - 4.0), length),
+        None => Note::rest(length),
+    })
+}
+
+enum Command {
+    Play(Melody),
+    Stop,
+    Shutdown,
+}
+
+/// Plays `Melody`s on a `ToneOutput` from a background thread.
+pub struct TonePlayer {
+    commands: Sender<Command>,
+    last_error: Arc<Mutex<Option<GpioError>>>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl TonePlayer {
+    /// Take over `output`, nothing plays until `play` is called.
+    pub fn new<T: ToneOutput + Send + 'static>(output: T) -> Self {
+        let (commands, command_receiver) = channel();
+        let last_error = Arc::new(Mutex::new(None));
+        let worker = {
+            let last_error = last_error.clone();
+            thread::spawn(move || {
+                let mut output = output;
+                let mut next_command = None;
+
+                loop {
+                    let command = match next_command.take() {
+                        Some(command) => command,
+                        None => command_receiver.recv().unwrap_or(Command::Shutdown),
+                    };
+
+                    let result = match command {
+                        Command::Play(melody) => TonePlayer::play_melody(&mut output, &melody, &command_receiver)
+                            .map(|interrupted_by| next_command = interrupted_by),
+                        Command::Stop => output.silence(),
+                        Command::Shutdown => {
+                            let _ = output.silence();
+                            return;
+                        }
+                    };
+
+                    if let Err(error) = result {
+                        let _ = output.silence();
+                        *last_error.lock().unwrap() = Some(error);
+                    }
+                }
+            })
+        };
+
+        TonePlayer { commands, last_error, worker: Some(worker) }
+    }
+
+    // Play `melody` until it's done, or return the command which interrupted it.
+    fn play_melody<T: ToneOutput>(output: &mut T, melody: &Melody, commands: &Receiver<Command>) -> Result<Option<Command>> {
+        let rounds = match melody.repeat {
+            Repeat::Times(times) => Some(times),
+            Repeat::Forever => None,
+        };
+
+        let wait = |duration: Duration| match commands.recv_timeout(duration) {
+            Ok(command) => Some(command),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Command::Shutdown),
+        };
+
+        let mut round = 0;
+        while !melody.notes.is_empty() && rounds.map_or(true, |times| round < times) {
+            for note in melody.notes.iter() {
+                let sounding = match note.frequency {
+                    Some(frequency) => {
+                        output.tone(frequency)?;
+                        note.duration.mul_f64(ARTICULATION)
+                    }
+                    None => Duration::from_millis(0),
+                };
+
+                if let Some(command) = wait(sounding) {
+                    return Ok(Some(command));
+                }
+                output.silence()?;
+                if let Some(command) = wait(note.duration - sounding) {
+                    return Ok(Some(command));
+                }
+            }
+            round += 1;
+        }
+
+        Ok(None)
+    }
+
+    /// Switch to `melody`, the current one stops right away.
+    pub fn play(&self, melody: Melody) {
+        let _ = self.commands.send(Command::Play(melody));
+    }
+
+    /// Stop playing.
+    pub fn stop(&self) {
+        let _ = self.commands.send(Command::Stop);
+    }
+
+    /// The error which stopped the last melody, if any, e.g. the PWM chip went away.
+    pub fn take_error(&self) -> Option<GpioError> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+impl Drop for TonePlayer {
+    /// Stop the thread, the output ends silent.
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // `Some(frequency)` for every tone, `None` for every silence.
+    #[derive(Clone, Default)]
+    struct RecordingBuzzer {
+        calls: Arc<Mutex<Vec<Option<f64>>>>,
+    }
+
+    impl ToneOutput for RecordingBuzzer {
+        fn tone(&mut self, frequency: f64) -> Result<()> {
+            self.calls.lock().unwrap().push(Some(frequency));
+            Ok(())
+        }
+
+        fn silence(&mut self) -> Result<()> {
+            self.calls.lock().unwrap().push(None);
+            Ok(())
+        }
+    }
+
+    fn rounded(note: &Note) -> (Option<i64>, u64) {
+        (note.frequency.map(|frequency| frequency.round() as i64), note.duration.as_millis() as u64)
+    }
+
+    #[test]
+    fn rtttl_should_be_parsed_into_notes() {
+        let melody = Melody::from_rtttl("test:d=4,o=5,b=120:8a,c6,p,2e.,16g#").unwrap();
+        let notes: Vec<(Option<i64>, u64)> = melody.notes.iter().map(rounded).collect();
+
+        assert_eq!(notes, vec![
+            (Some(880), 250),
+            (Some(1047), 500),
+            (None, 500),
+            (Some(659), 1500),
+            (Some(831), 125),
+        ]);
+        assert_eq!(melody.repeat, Repeat::Times(1));
+    }
+
+    #[test]
+    fn bad_rtttl_should_be_rejected() {
+        assert_eq!(Melody::from_rtttl("no sections").is_err(), true);
+        assert_eq!(Melody::from_rtttl("x:d=4,o=5,b=0:a").unwrap_err().to_string().contains("tempo"), true);
+        assert_eq!(Melody::from_rtttl("x:d=4,o=5,b=120:3a").is_err(), true);
+        assert_eq!(Melody::from_rtttl("x:d=4,o=5,b=120:z").is_err(), true);
+    }
+
+    #[test]
+    fn player_should_play_the_notes_and_end_silent() {
+        let buzzer = RecordingBuzzer::default();
+        let player = TonePlayer::new(buzzer.clone());
+
+        player.play(Melody::new(vec![Note::tone(440.0, ms(10)), Note::rest(ms(10)), Note::tone(880.0, ms(10))], Repeat::Times(1)));
+        thread::sleep(ms(100));
+        drop(player);
+
+        assert_eq!(*buzzer.calls.lock().unwrap(), vec![Some(440.0), None, None, Some(880.0), None, None]);
+    }
+}
@@ -0,0 +1,54 @@
+// Time source used by the blocking helpers.
+//
+// On the Pi we use `SystemClock` (real sleep). Tests use `MockClock` (see
+// `mock.rs`) so that "sleep 10ms" just moves virtual time forward.
+
+use std::{ thread, time::{ Duration, Instant } };
+
+/// Something which can tell the time and sleep.
+pub trait Clock {
+    /// Time elapsed since the clock was created.
+    fn now(&self) -> Duration;
+
+    /// Block (or pretend to block) for the given duration.
+    fn sleep(&self, duration: Duration);
+}
+
+/// Wall clock, `sleep` is `thread::sleep`.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    /// Create a clock which starts counting from now.
+    pub fn new() -> Self {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
@@ -0,0 +1,410 @@
+// Button debouncing.
+//
+// A mechanical button doesn't go cleanly from `HIGH` to `LOW`, the contacts
+// bounce for a few ms first. `Debouncer` only accepts a new state after the
+// raw input kept it for `stable_time`, so one tap is exactly one press.
+//
+// Non-blocking use (e.g. inside your own loop):
+//
+// ```rust
+// let mut debouncer = Debouncer::new(DebounceConfig::default());
+// loop {
+//     if let Some(ButtonEdge::Released) = debouncer.poll(&button, &clock) { ... }
+//     clock.sleep(debouncer.config().sample_period);
+// }
+// ```
+//
+// Blocking use:
+//
+// ```rust
+// debouncer.wait_for_press(&button);
+//
+// // Sleep on the pin interrupt while the button is idle, no CPU burnt polling
+// debouncer.wait_for_press_on_edges(&mut button)?;
+// ```
+
+use rppal::gpio::Level;
+use std::{ sync::atomic::AtomicBool, time::Duration };
+use crate::{
+    clock::{ Clock, SystemClock },
+    digital::{ ActiveLevel, DigitalInput },
+    edge::{ Edge, EdgeInput },
+    error::Result,
+    wait::WaitLimit,
+};
+
+/// Debouncing settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebounceConfig {
+    /// How long the raw input has to stay at a new level before we accept it.
+    pub stable_time: Duration,
+    /// How often the blocking helpers sample the pin.
+    pub sample_period: Duration,
+    /// Which level means pressed, `None` takes the pin's own
+    /// `DigitalInput::active_level()`. Only set it to override the pin.
+    pub active_level: Option<ActiveLevel>,
+}
+
+impl Default for DebounceConfig {
+    /// `20ms` stable time, sampling every `2ms`, active level of the pin.
+    fn default() -> Self {
+        DebounceConfig {
+            stable_time: Duration::from_millis(20),
+            sample_period: Duration::from_millis(2),
+            active_level: None,
+        }
+    }
+}
+
+/// Debounced state change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEdge {
+    Pressed,
+    Released,
+}
+
+/// Debounce state machine, see the module comment.
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    config: DebounceConfig,
+    // Accepted state, `None` until the first stable reading.
+    stable_pressed: Option<bool>,
+    // Raw state which differs from `stable_pressed` and since when.
+    candidate: Option<(bool, Duration)>,
+    // A `Pressed` edge was reported and the release hasn't happened yet.
+    press_reported: bool,
+}
+
+impl Debouncer {
+    /// Create a debouncer which doesn't know the button state yet.
+    pub fn new(config: DebounceConfig) -> Self {
+        Debouncer { config, stable_pressed: None, candidate: None, press_reported: false }
+    }
+
+    /// Current settings.
+    pub fn config(&self) -> &DebounceConfig {
+        &self.config
+    }
+
+    /// Debounced state, `false` until the first stable reading.
+    pub fn is_pressed(&self) -> bool {
+        self.stable_pressed.unwrap_or(false)
+    }
+
+    /// `true` while a new raw level waits to become stable, i.e. the caller
+    /// should keep sampling instead of waiting for the next edge.
+    pub fn is_settling(&self) -> bool {
+        self.stable_pressed.is_none() || self.candidate.is_some()
+    }
+
+    /// Which level of `pin` means pressed: the `active_level` override, or
+    /// else the pin's own.
+    pub fn active_level_of<P: DigitalInput + ?Sized>(&self, pin: &P) -> ActiveLevel {
+        self.config.active_level.unwrap_or_else(|| pin.active_level())
+    }
+
+    /// Whether `pin` reads pressed right now, not debounced.
+    pub fn reads_pressed<P: DigitalInput + ?Sized>(&self, pin: &P) -> bool {
+        pin.read() == self.active_level_of(pin).level()
+    }
+
+    /// Feed one raw sample (`pressed` or not) taken at `now`. Return the edge
+    /// if the debounced state changed. The very first stable state is not
+    /// reported as an edge, so a button held at startup doesn't count as a press.
+    pub fn update(&mut self, pressed: bool, now: Duration) -> Option<ButtonEdge> {
+
+        if self.stable_pressed == Some(pressed) {
+            self.candidate = None;
+            return None;
+        }
+
+        let since = match self.candidate {
+            Some((candidate_pressed, since)) if candidate_pressed == pressed => since,
+            _ => {
+                self.candidate = Some((pressed, now));
+                now
+            }
+        };
+
+        if now.checked_sub(since).unwrap_or_default() < self.config.stable_time {
+            return None;
+        }
+
+        let first_reading = self.stable_pressed.is_none();
+        self.stable_pressed = Some(pressed);
+        self.candidate = None;
+
+        if first_reading {
+            return None;
+        }
+
+        self.press_reported = pressed;
+        if pressed { Some(ButtonEdge::Pressed) } else { Some(ButtonEdge::Released) }
+    }
+
+    /// Non-blocking: sample `pin` once at `clock.now()`.
+    pub fn poll<P, C>(&mut self, pin: &P, clock: &C) -> Option<ButtonEdge>
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        self.update(self.reads_pressed(pin), clock.now())
+    }
+
+    /// Blocking: only return when a debounced press and then release happens.
+    pub fn wait_for_press<P: DigitalInput + ?Sized>(&mut self, pin: &P) {
+        self.wait_for_press_with_clock(pin, &SystemClock::new())
+    }
+
+    /// Same with `wait_for_press`, but give up after `timeout`.
+    /// Return `true` if the button was pressed.
+    pub fn wait_for_press_timeout<P: DigitalInput + ?Sized>(&mut self, pin: &P, timeout: Duration) -> bool {
+        self.wait_for_press_with_limit(pin, WaitLimit::timeout(timeout), &SystemClock::new())
+    }
+
+    /// Same with `wait_for_press`, but give up as soon as `cancel` becomes `true`.
+    /// Return `true` if the button was pressed.
+    pub fn wait_for_press_or_cancelled<P: DigitalInput + ?Sized>(&mut self, pin: &P, cancel: &AtomicBool) -> bool {
+        self.wait_for_press_with_limit(pin, WaitLimit::cancelled_by(cancel), &SystemClock::new())
+    }
+
+    /// Same with `wait_for_press`, but sampling time goes through the given `Clock`.
+    pub fn wait_for_press_with_clock<P, C>(&mut self, pin: &P, clock: &C)
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        self.wait_for_press_with_limit(pin, WaitLimit::forever(), clock);
+    }
+
+    /// The general form of `wait_for_press`: stop waiting when `limit` is
+    /// reached, sampling time goes through the given `Clock`.
+    /// Return `true` if the button was pressed.
+    pub fn wait_for_press_with_limit<P, C>(&mut self, pin: &P, limit: WaitLimit, clock: &C) -> bool
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        // Timestamps from another clock mean nothing here
+        self.candidate = None;
+
+        // A press reported before an earlier wait gave up still counts
+        let started = clock.now();
+        let mut pressed = self.press_reported;
+        loop {
+            match self.poll(pin, clock) {
+                Some(ButtonEdge::Pressed) => pressed = true,
+                Some(ButtonEdge::Released) if pressed => return true,
+                _ => {}
+            }
+
+            if limit.is_reached(started, clock.now()) {
+                return false;
+            }
+
+            clock.sleep(self.config.sample_period)
+        }
+    }
+
+    /// Same with `wait_for_press`, but while the button is idle the thread
+    /// sleeps on the pin's edge interrupt. The pin is only sampled from the
+    /// first edge until the press completes or the input settles again.
+    pub fn wait_for_press_on_edges<P: EdgeInput>(&mut self, pin: &mut P) -> Result<()> {
+        let clock = SystemClock::new();
+        let active_level = self.active_level_of(pin).level();
+        let press_edge = if active_level == Level::Low { Edge::Falling } else { Edge::Rising };
+
+        self.candidate = None;
+        let mut pressed = self.press_reported;
+        loop {
+            if !pressed && self.stable_pressed == Some(false) && self.candidate.is_none() {
+                // Subscribe before reading, a press in between is queued instead of lost
+                let edges = pin.subscribe_channel(press_edge)?;
+                if pin.read() != active_level {
+                    let _ = edges.recv();
+                }
+                pin.unsubscribe()?;
+            }
+
+            match self.poll(pin, &clock) {
+                Some(ButtonEdge::Pressed) => pressed = true,
+                Some(ButtonEdge::Released) if pressed => return Ok(()),
+                _ => {}
+            }
+
+            clock.sleep(self.config.sample_period)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{ GpioUtil, InputScript, MockGpio };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Press at 50ms bouncing until 70ms, held until 150ms, release with 2 bounces.
+    fn bouncy_press() -> InputScript {
+        InputScript::new()
+            .high_for(ms(50))
+            .low_for(ms(5)).high_for(ms(7)).low_for(ms(3)).high_for(ms(5))
+            .low_for(ms(80))
+            .high_for(ms(3)).low_for(ms(2))
+            .high()
+    }
+
+    #[test]
+    fn debouncer_should_report_one_press_for_bouncy_input() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let button = gpio.input_pin(17, bouncy_press());
+        let mut debouncer = Debouncer::new(DebounceConfig::default());
+
+        let mut edges = Vec::new();
+        while clock.now() < ms(300) {
+            if let Some(edge) = debouncer.poll(&button, &clock) {
+                edges.push((edge, clock.now()));
+            }
+            clock.sleep(ms(1));
+        }
+
+        assert_eq!(edges, vec![
+            (ButtonEdge::Pressed, ms(90)),
+            (ButtonEdge::Released, ms(175)),
+        ]);
+    }
+
+    #[test]
+    fn wait_for_press_should_return_once_per_bouncy_press() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let button = gpio.input_pin(17, bouncy_press());
+
+        // Without debouncing, the first bounce already counts as a full press
+        GpioUtil::block_until_button_pressed_with_clock(&button, &clock);
+        assert!(clock.now() < ms(150));
+
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let button = gpio.input_pin(17, bouncy_press());
+        let mut debouncer = Debouncer::new(DebounceConfig::default());
+        debouncer.wait_for_press_with_clock(&button, &clock);
+        assert!(clock.now() >= ms(170) && clock.now() < ms(180));
+    }
+
+    #[test]
+    fn wait_for_press_with_limit_should_time_out_during_a_long_press() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let button = gpio.input_pin(17, bouncy_press());
+        let mut debouncer = Debouncer::new(DebounceConfig::default());
+
+        // Pressed, but not released yet at 120ms
+        assert_eq!(debouncer.wait_for_press_with_limit(&button, WaitLimit::timeout(ms(120)), &clock), false);
+        assert_eq!(debouncer.is_pressed(), true);
+
+        // Keep waiting, the release completes the press
+        assert_eq!(debouncer.wait_for_press_with_limit(&button, WaitLimit::timeout(ms(120)), &clock), true);
+        assert!(clock.now() >= ms(170) && clock.now() < ms(180));
+    }
+
+    #[test]
+    fn wait_for_press_on_edges_should_return_after_release() {
+        use crate::{ MemoryPin, PollingInput };
+        use std::thread;
+
+        let button = MemoryPin::new(Level::High);
+        let finger = button.clone();
+        let mut input = PollingInput::new(button, ms(1));
+
+        let presser = thread::spawn(move || {
+            thread::sleep(ms(100));
+            finger.set_level(Level::Low);
+            thread::sleep(ms(100));
+            finger.set_level(Level::High);
+        });
+
+        let mut debouncer = Debouncer::new(DebounceConfig::default());
+        debouncer.wait_for_press_on_edges(&mut input).unwrap();
+        assert_eq!(input.is_high(), true);
+        presser.join().unwrap();
+    }
+
+    #[test]
+    fn press_while_arming_the_interrupt_should_not_be_lost() {
+        use crate::MemoryPin;
+        use std::{ sync::mpsc::channel, thread };
+
+        // The press lands while the interrupt is being set up, so there's no
+        // edge for it, only the level tells
+        struct LatePress {
+            pin: MemoryPin,
+            callback: Option<Box<dyn FnMut(Level) + Send>>,
+        }
+
+        impl DigitalInput for LatePress {
+            fn read(&self) -> Level {
+                self.pin.read()
+            }
+        }
+
+        impl EdgeInput for LatePress {
+            fn subscribe<F>(&mut self, _edge: Edge, callback: F) -> Result<()>
+            where
+                F: FnMut(Level) + Send + 'static,
+            {
+                self.callback = Some(Box::new(callback));
+                self.pin.set_level(Level::Low);
+                Ok(())
+            }
+
+            fn unsubscribe(&mut self) -> Result<()> {
+                self.callback = None;
+                Ok(())
+            }
+        }
+
+        let button = MemoryPin::new(Level::High);
+        let finger = button.clone();
+        let (done, finished) = channel();
+        thread::spawn(move || {
+            let mut debouncer = Debouncer::new(DebounceConfig::default());
+            let result = debouncer.wait_for_press_on_edges(&mut LatePress { pin: button, callback: None });
+            let _ = done.send(result.is_ok());
+        });
+
+        thread::sleep(ms(100));
+        finger.set_level(Level::High);
+        assert_eq!(finished.recv_timeout(ms(500)), Ok(true));
+    }
+
+    #[test]
+    fn debouncer_should_take_the_active_level_from_the_pin() {
+        use crate::{ ConfiguredInput, InputConfig };
+
+        // Switch to 3.3V: pressed is `HIGH`, rest of the script inverted
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let script = InputScript::new().low_for(ms(50)).high_for(ms(100)).low();
+        let button = ConfiguredInput::new(gpio.input_pin(17, script), InputConfig::switch_to_3v3().active_level);
+        let mut debouncer = Debouncer::new(DebounceConfig::default());
+
+        let mut edges = Vec::new();
+        while clock.now() < ms(300) {
+            if let Some(edge) = debouncer.poll(&button, &clock) {
+                edges.push((edge, clock.now()));
+            }
+            clock.sleep(ms(1));
+        }
+        assert_eq!(edges, vec![(ButtonEdge::Pressed, ms(70)), (ButtonEdge::Released, ms(170))]);
+
+        // An explicit override wins over the pin
+        let inverted = Debouncer::new(DebounceConfig { active_level: Some(ActiveLevel::Low), ..Default::default() });
+        assert_eq!(inverted.active_level_of(&button), ActiveLevel::Low);
+        assert_eq!(debouncer.active_level_of(&button), ActiveLevel::High);
+    }
+}
@@ -0,0 +1,134 @@
+// Digital pin abstraction.
+//
+// `GpioUtil` only talks to these traits, so the same code can drive a real
+// `rppal` pin on the Pi or an in-memory pin (see `memory.rs`) on a laptop/CI.
+
+use rppal::gpio::{ InputPin, OutputPin, Level };
+
+/// Which input level means "the button is pressed".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActiveLevel {
+    /// Pressed pulls the signal `LOW` (button modules with pull-up, the default).
+    Low,
+    /// Pressed pulls the signal `HIGH`.
+    High,
+}
+
+impl ActiveLevel {
+    /// The level which means pressed.
+    pub fn level(self) -> Level {
+        match self {
+            ActiveLevel::Low => Level::Low,
+            ActiveLevel::High => Level::High,
+        }
+    }
+}
+
+/// A pin we can read a digital level from.
+pub trait DigitalInput {
+    /// Read the current level.
+    fn read(&self) -> Level;
+
+    /// `true` when the pin reads `Level::High`.
+    fn is_high(&self) -> bool {
+        self.read() == Level::High
+    }
+
+    /// `true` when the pin reads `Level::Low`.
+    fn is_low(&self) -> bool {
+        self.read() == Level::Low
+    }
+
+    /// Which level means pressed (or active), `LOW` unless the pin was
+    /// configured otherwise, see `ConfiguredInput`.
+    fn active_level(&self) -> ActiveLevel {
+        ActiveLevel::Low
+    }
+
+    /// `true` when the pin reads its active level, whatever the wiring.
+    fn is_pressed(&self) -> bool {
+        self.read() == self.active_level().level()
+    }
+}
+
+/// A pin we can drive to a digital level.
+pub trait DigitalOutput {
+    /// Drive the pin `HIGH`.
+    fn set_high(&mut self);
+
+    /// Drive the pin `LOW`.
+    fn set_low(&mut self);
+
+    /// `true` when the pin is currently driven `HIGH`.
+    fn is_set_high(&self) -> bool;
+
+    /// `true` when the pin is currently driven `LOW`.
+    fn is_set_low(&self) -> bool {
+        !self.is_set_high()
+    }
+
+    /// Drive the pin to the given level.
+    fn write(&mut self, level: Level) {
+        match level {
+            Level::Low => self.set_low(),
+            Level::High => self.set_high(),
+        }
+    }
+
+    /// Flip the current output level.
+    fn toggle(&mut self) {
+        if self.is_set_high() { self.set_low() } else { self.set_high() }
+    }
+}
+
+// ----------------------------------- rppal backend ---------------------------------------------
+
+impl DigitalInput for InputPin {
+    fn read(&self) -> Level {
+        InputPin::read(self)
+    }
+}
+
+impl DigitalOutput for OutputPin {
+    fn set_high(&mut self) {
+        OutputPin::set_high(self)
+    }
+
+    fn set_low(&mut self) {
+        OutputPin::set_low(self)
+    }
+
+    fn is_set_high(&self) -> bool {
+        OutputPin::is_set_high(self)
+    }
+
+    fn toggle(&mut self) {
+        OutputPin::toggle(self)
+    }
+}
+
+// Allow passing boxed pins around (e.g. `Box<dyn DigitalOutput + Send>`).
+
+impl<T: DigitalInput + ?Sized> DigitalInput for Box<T> {
+    fn read(&self) -> Level {
+        (**self).read()
+    }
+
+    fn active_level(&self) -> ActiveLevel {
+        (**self).active_level()
+    }
+}
+
+impl<T: DigitalOutput + ?Sized> DigitalOutput for Box<T> {
+    fn set_high(&mut self) {
+        (**self).set_high()
+    }
+
+    fn set_low(&mut self) {
+        (**self).set_low()
+    }
+
+    fn is_set_high(&self) -> bool {
+        (**self).is_set_high()
+    }
+}
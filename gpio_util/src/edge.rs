@@ -0,0 +1,234 @@
+// Edge-triggered inputs.
+//
+// Instead of reading a pin every 10ms, subscribe to its edges and let the
+// kernel wake us up:
+//
+// ```rust
+// let mut button = GpioUtil::create_input_pin(17)?;
+//
+// // Callback, called on a background thread
+// button.subscribe(Edge::Falling, |level| println!("pressed, now {}", level))?;
+//
+// // Or channel
+// let edges = button.subscribe_channel(Edge::Both)?;
+// for level in edges.iter() { ... }
+//
+// // Or just block until the next edge
+// button.wait_for_edge(Edge::Rising, Some(Duration::from_secs(5)))?;
+// ```
+//
+// `rppal` pins use the GPIO character device interrupts. Pins without
+// interrupts (e.g. `MemoryPin`) can be wrapped in `PollingInput`, which does
+// the polling on its own thread. For the scripted `MockGpio` pins, use
+// `GpioUtil::wait_for_edge_with_clock` so the polling runs on virtual time.
+
+use rppal::gpio::{ InputPin, Level, Trigger };
+use std::{
+    sync::{
+        atomic::{ AtomicBool, Ordering },
+        mpsc::{ channel, Receiver, RecvTimeoutError },
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use crate::{ digital::{ ActiveLevel, DigitalInput }, error::Result };
+
+/// Which level changes to report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// `LOW -> HIGH`
+    Rising,
+    /// `HIGH -> LOW`
+    Falling,
+    /// Any change.
+    Both,
+}
+
+impl Edge {
+    /// `true` if changing to `new_level` is this kind of edge.
+    pub fn matches(self, new_level: Level) -> bool {
+        match self {
+            Edge::Rising => new_level == Level::High,
+            Edge::Falling => new_level == Level::Low,
+            Edge::Both => true,
+        }
+    }
+
+    /// The `rppal` interrupt trigger for this edge.
+    pub fn trigger(self) -> Trigger {
+        match self {
+            Edge::Rising => Trigger::RisingEdge,
+            Edge::Falling => Trigger::FallingEdge,
+            Edge::Both => Trigger::Both,
+        }
+    }
+}
+
+/// An input which can report its edges without the caller polling it.
+pub trait EdgeInput: DigitalInput {
+    /// Call `callback` with the new level on every matching edge, from a
+    /// background thread. Replace any previous subscription.
+    fn subscribe<F>(&mut self, edge: Edge, callback: F) -> Result<()>
+    where
+        F: FnMut(Level) + Send + 'static;
+
+    /// Stop the current subscription (if any).
+    fn unsubscribe(&mut self) -> Result<()>;
+
+    /// Same with `subscribe`, but deliver the new levels to a channel.
+    fn subscribe_channel(&mut self, edge: Edge) -> Result<Receiver<Level>> {
+        let (sender, receiver) = channel();
+        self.subscribe(edge, move |level| { let _ = sender.send(level); })?;
+        Ok(receiver)
+    }
+
+    /// Block until the next matching edge and return the new level, or
+    /// `None` if `timeout` passed first. `None` timeout waits forever.
+    fn wait_for_edge(&mut self, edge: Edge, timeout: Option<Duration>) -> Result<Option<Level>> {
+        let edges = self.subscribe_channel(edge)?;
+        let level = match timeout {
+            Some(timeout) => match edges.recv_timeout(timeout) {
+                Ok(level) => Some(level),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+            },
+            None => edges.recv().ok(),
+        };
+
+        self.unsubscribe()?;
+        Ok(level)
+    }
+}
+
+// ----------------------------------- rppal backend ---------------------------------------------
+
+impl EdgeInput for InputPin {
+    fn subscribe<F>(&mut self, edge: Edge, callback: F) -> Result<()>
+    where
+        F: FnMut(Level) + Send + 'static,
+    {
+        Ok(self.set_async_interrupt(edge.trigger(), callback)?)
+    }
+
+    fn unsubscribe(&mut self) -> Result<()> {
+        Ok(self.clear_async_interrupt()?)
+    }
+
+    fn wait_for_edge(&mut self, edge: Edge, timeout: Option<Duration>) -> Result<Option<Level>> {
+        self.set_interrupt(edge.trigger())?;
+        let level = self.poll_interrupt(true, timeout);
+        self.clear_interrupt()?;
+        Ok(level?)
+    }
+}
+
+// ----------------------------------- polling fallback ------------------------------------------
+
+/// Give any input an `EdgeInput` interface by polling it on a background
+/// thread every `poll_period`. Short pulses between two polls are missed, so
+/// prefer real interrupts when the pin has them.
+#[derive(Debug)]
+pub struct PollingInput<P> {
+    pin: P,
+    poll_period: Duration,
+    poller: Option<(Arc<AtomicBool>, thread::JoinHandle<()>)>,
+}
+
+impl<P: DigitalInput + Clone + Send + 'static> PollingInput<P> {
+    /// Wrap `pin`, the poller thread only runs while subscribed.
+    pub fn new(pin: P, poll_period: Duration) -> Self {
+        PollingInput { pin, poll_period, poller: None }
+    }
+
+    /// The wrapped pin.
+    pub fn pin(&self) -> &P {
+        &self.pin
+    }
+}
+
+impl<P> PollingInput<P> {
+    fn stop_poller(&mut self) {
+        if let Some((stop, handle)) = self.poller.take() {
+            stop.store(true, Ordering::SeqCst);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<P: DigitalInput> DigitalInput for PollingInput<P> {
+    fn read(&self) -> Level {
+        self.pin.read()
+    }
+
+    fn active_level(&self) -> ActiveLevel {
+        self.pin.active_level()
+    }
+}
+
+impl<P: DigitalInput + Clone + Send + 'static> EdgeInput for PollingInput<P> {
+    fn subscribe<F>(&mut self, edge: Edge, mut callback: F) -> Result<()>
+    where
+        F: FnMut(Level) + Send + 'static,
+    {
+        self.stop_poller();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let pin = self.pin.clone();
+        let poll_period = self.poll_period;
+
+        // Take the starting level now, changes right after `subscribe` returns count as edges
+        let mut last_level = pin.read();
+        let handle = thread::spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                thread::sleep(poll_period);
+
+                let level = pin.read();
+                if level != last_level && edge.matches(level) {
+                    callback(level);
+                }
+                last_level = level;
+            }
+        });
+
+        self.poller = Some((stop, handle));
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self) -> Result<()> {
+        self.stop_poller();
+        Ok(())
+    }
+}
+
+impl<P> Drop for PollingInput<P> {
+    fn drop(&mut self) {
+        self.stop_poller()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::MemoryPin;
+
+    #[test]
+    fn polling_input_should_report_matching_edges_only() {
+        let button = MemoryPin::new(Level::High);
+        let mut input = PollingInput::new(button.clone(), Duration::from_millis(1));
+        let edges = input.subscribe_channel(Edge::Falling).unwrap();
+
+        button.set_level(Level::Low);
+        assert_eq!(edges.recv_timeout(Duration::from_secs(1)), Ok(Level::Low));
+
+        button.set_level(Level::High);
+        assert_eq!(edges.recv_timeout(Duration::from_millis(50)).is_err(), true);
+    }
+
+    #[test]
+    fn wait_for_edge_should_time_out() {
+        let mut input = PollingInput::new(MemoryPin::new(Level::High), Duration::from_millis(1));
+        assert_eq!(input.wait_for_edge(Edge::Both, Some(Duration::from_millis(20))).unwrap(), None);
+    }
+}
@@ -0,0 +1,244 @@
+// Errors of `gpio_util`, with a hint about how to fix the usual setup problems.
+//
+// `rppal` errors convert with `?`. The `pcf8591` (I2C) errors need the bus
+// path to say something useful, so they go through `GpioError::from_i2c`:
+//
+// ```rust
+// fn main() -> gpio_util::Result<()> {
+//     let button = GpioUtil::create_input_pin(17)?;
+//     let mut converter = PCF8591::new(I2C_BUS, 0x48, 3.3).map_err(|error| GpioError::from_i2c(I2C_BUS, error))?;
+//     ...
+// }
+// ```
+//
+// `main` prints the returned error with `Debug`, so `Debug` prints the same
+// message as `Display`:
+//
+// `Error: permission denied on `/dev/gpiomem`, run with `sudo`, or ...`
+
+use rppal::{ gpio, gpio::Mode, pwm };
+use std::{ error, fmt, io };
+use crate::registry::PinMode;
+
+/// `Result` of `gpio_util`.
+pub type Result<T> = std::result::Result<T, GpioError>;
+
+/// Everything which can go wrong when talking to the pins, I2C or PWM.
+pub enum GpioError {
+    /// No access to the device file, e.g. `/dev/gpiomem` or `/dev/i2c-1`.
+    PermissionDenied(String),
+    /// The BCM pin is already taken by another pin instance in this program.
+    PinInUse(u8),
+    /// The BCM pin is claimed in the `PinRegistry` by `owner`.
+    PinClaimed { pin: u8, mode: PinMode, owner: String },
+    /// The BCM pin is switched to an alternate function by a device tree
+    /// overlay (I2C, SPI, UART, PWM...).
+    PinReserved { pin: u8, mode: Mode },
+    /// The GPIO peripheral has no pin with this BCM number.
+    PinNotAvailable(u8),
+    /// The physical header pin is a power or ground pin.
+    NotAGpio { physical: u8, name: &'static str },
+    /// There is no such physical pin on the 40 pin header.
+    InvalidPhysicalPin(u8),
+    /// The wiringPi number doesn't map to a header GPIO.
+    InvalidWiringPiPin(u8),
+    /// The I2C bus device doesn't exist, I2C isn't enabled.
+    I2cBusMissing(String),
+    /// Other I2C error, with the bus it happened on.
+    I2c { bus: String, source: io::Error },
+    /// `/sys/class/pwm/pwmchip0` doesn't exist, the PWM overlay isn't loaded.
+    PwmChipMissing,
+    /// Frequency or duty cycle out of range.
+    InvalidPwmSetting(String),
+    /// Output timing which can't be played, e.g. a Morse speed of 0 wpm.
+    InvalidTiming(String),
+    /// The wiring file can't be read, parsed, or doesn't make sense.
+    Wiring(String),
+    /// The `Ctrl+C` handler can't be installed, e.g. there's one already.
+    SignalHandler(String),
+    /// `rppal` can't tell which Raspberry Pi this is.
+    UnknownModel,
+    /// Interrupt thread panicked.
+    ThreadPanic,
+    /// The edge subscription ended (e.g. the pin unsubscribed elsewhere)
+    /// while still waiting for edges.
+    SubscriptionEnded,
+    /// Other I/O error.
+    Io(io::Error),
+}
+
+const GPIOMEM: &str = "/dev/gpiomem";
+const PWM_CHIP: &str = "/sys/class/pwm/pwmchip0";
+
+// Highest BCM pin on the 40 pin header, higher ones aren't "in use", they don't exist for us.
+const LAST_HEADER_BCM_PIN: u8 = 27;
+
+impl GpioError {
+    /// Wrap an I2C error (e.g. `pcf8591::LinuxI2CError`) which happened on `bus`.
+    pub fn from_i2c<E: Into<io::Error>>(bus: &str, error: E) -> Self {
+        let error = error.into();
+        match error.kind() {
+            io::ErrorKind::NotFound => GpioError::I2cBusMissing(bus.to_string()),
+            io::ErrorKind::PermissionDenied => GpioError::PermissionDenied(bus.to_string()),
+            _ => GpioError::I2c { bus: bus.to_string(), source: error },
+        }
+    }
+}
+
+impl From<gpio::Error> for GpioError {
+    fn from(error: gpio::Error) -> Self {
+        match error {
+            gpio::Error::PermissionDenied(path) => GpioError::PermissionDenied(path),
+            gpio::Error::PinNotAvailable(pin) if pin <= LAST_HEADER_BCM_PIN => GpioError::PinInUse(pin),
+            gpio::Error::PinNotAvailable(pin) => GpioError::PinNotAvailable(pin),
+            gpio::Error::UnknownModel => GpioError::UnknownModel,
+            gpio::Error::ThreadPanic => GpioError::ThreadPanic,
+            gpio::Error::Io(error) => GpioError::Io(error),
+        }
+    }
+}
+
+impl From<pwm::Error> for GpioError {
+    fn from(error: pwm::Error) -> Self {
+        match error {
+            pwm::Error::Io(ref error) if error.kind() == io::ErrorKind::NotFound => GpioError::PwmChipMissing,
+            pwm::Error::Io(ref error) if error.kind() == io::ErrorKind::PermissionDenied => GpioError::PermissionDenied(PWM_CHIP.to_string()),
+            pwm::Error::Io(error) => GpioError::Io(error),
+        }
+    }
+}
+
+impl From<io::Error> for GpioError {
+    fn from(error: io::Error) -> Self {
+        GpioError::Io(error)
+    }
+}
+
+impl fmt::Display for GpioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpioError::PermissionDenied(path) if path == GPIOMEM => write!(
+                f,
+                "permission denied on `{}`, run with `sudo`, or give the `gpio` group access to it: \
+                 `sudo groupadd -f --system gpio && sudo usermod -aG gpio $USER`, add the udev rule \
+                 `SUBSYSTEM==\"bcm2835-gpiomem\", GROUP=\"gpio\", MODE=\"0660\"` and log in again",
+                path
+            ),
+            GpioError::PermissionDenied(path) => write!(
+                f,
+                "permission denied on `{}`, run with `sudo`, or add your user to the group owning it \
+                 (see `ls -l {}`) with `sudo usermod -aG <group> $USER` and log in again",
+                path, path
+            ),
+            GpioError::PinInUse(pin) => write!(
+                f,
+                "BCM pin {} is already in use, drop the other pin instance before creating a new one",
+                pin
+            ),
+            GpioError::PinClaimed { pin, mode, owner } => write!(
+                f,
+                "BCM pin {} is already claimed as {} by `{}`, release it first or pick another pin",
+                pin, mode, owner
+            ),
+            GpioError::PinReserved { pin, mode } => write!(
+                f,
+                "BCM pin {} is in mode `{}`, reserved by a device tree overlay (I2C, SPI, UART, PWM...), \
+                 pick another pin or disable the overlay in `/boot/firmware/usercfg.txt`",
+                pin, mode
+            ),
+            GpioError::PinNotAvailable(pin) => write!(
+                f,
+                "BCM pin {} doesn't exist, the 40 pin header has BCM 0 to {}",
+                pin, LAST_HEADER_BCM_PIN
+            ),
+            GpioError::NotAGpio { physical, name } => write!(
+                f,
+                "physical pin {} is {}, not a GPIO, check the wiring",
+                physical, name
+            ),
+            GpioError::InvalidPhysicalPin(physical) => write!(
+                f,
+                "physical pin {} doesn't exist, the header has pins 1 to 40",
+                physical
+            ),
+            GpioError::InvalidWiringPiPin(wiring_pi) => write!(
+                f,
+                "wiringPi pin {} doesn't exist on the 40 pin header, see `gpio readall`",
+                wiring_pi
+            ),
+            GpioError::I2cBusMissing(bus) => write!(
+                f,
+                "I2C bus `{}` doesn't exist, add `dtparam=i2c_arm=on` to `/boot/firmware/usercfg.txt` and reboot",
+                bus
+            ),
+            GpioError::I2c { bus, source } => write!(f, "I2C error on `{}`: {}", bus, source),
+            GpioError::PwmChipMissing => write!(
+                f,
+                "`{}` doesn't exist, add `dtoverlay=pwm-2chan` to `/boot/firmware/usercfg.txt` and reboot",
+                PWM_CHIP
+            ),
+            GpioError::InvalidPwmSetting(message) => write!(f, "invalid PWM setting: {}", message),
+            GpioError::InvalidTiming(message) => write!(f, "invalid timing: {}", message),
+            GpioError::Wiring(message) => write!(f, "wiring: {}", message),
+            GpioError::SignalHandler(message) => write!(f, "can't install the signal handler: {}", message),
+            GpioError::UnknownModel => write!(f, "unknown Raspberry Pi model, try the latest `rppal`"),
+            GpioError::ThreadPanic => write!(f, "GPIO interrupt thread panicked"),
+            GpioError::SubscriptionEnded => write!(
+                f,
+                "the pin's edge subscription ended while waiting, is something else unsubscribing it?"
+            ),
+            GpioError::Io(error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+impl fmt::Debug for GpioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl error::Error for GpioError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            GpioError::I2c { source, .. } => Some(source),
+            GpioError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn rppal_errors_should_convert_to_the_matching_variant() {
+        let in_use = GpioError::from(gpio::Error::PinNotAvailable(17));
+        let missing = GpioError::from(gpio::Error::PinNotAvailable(60));
+        let denied = GpioError::from(gpio::Error::PermissionDenied(GPIOMEM.to_string()));
+
+        assert_eq!(matches!(in_use, GpioError::PinInUse(17)), true);
+        assert_eq!(matches!(missing, GpioError::PinNotAvailable(60)), true);
+        assert_eq!(denied.to_string().contains("gpio"), true);
+    }
+
+    #[test]
+    fn i2c_errors_should_point_at_the_bus() {
+        let missing = GpioError::from_i2c("/dev/i2c-1", io::Error::from(io::ErrorKind::NotFound));
+        let denied = GpioError::from_i2c("/dev/i2c-1", io::Error::from(io::ErrorKind::PermissionDenied));
+
+        assert_eq!(matches!(missing, GpioError::I2cBusMissing(_)), true);
+        assert_eq!(missing.to_string().contains("i2c_arm=on"), true);
+        assert_eq!(denied.to_string().contains("/dev/i2c-1"), true);
+    }
+
+    #[test]
+    fn missing_pwm_chip_should_be_detected() {
+        let error = GpioError::from(pwm::Error::Io(io::Error::from(io::ErrorKind::NotFound)));
+
+        assert_eq!(matches!(error, GpioError::PwmChipMissing), true);
+        assert_eq!(format!("{:?}", error), error.to_string());
+    }
+}
@@ -0,0 +1,313 @@
+// Button gestures on top of the debounced press/release edges.
+//
+// - `Click`: short press, no second press within `double_click_window`.
+// - `DoubleClick`: two short presses, the second one starts within `double_click_window`.
+// - `LongPress(held)`: released after holding at least `long_press_time`.
+// - `HoldRepeat`: sent while holding, first after `hold_repeat_delay` and then
+//   every `hold_repeat_interval` (e.g. for "hold to keep increasing").
+//
+// A `Click` is only reported once the double click window is over, set
+// `double_click_window` to `0` if you don't need double clicks and want the
+// click right on release.
+//
+// ```rust
+// let mut gestures = GestureDetector::new(DebounceConfig::default(), GestureConfig::default());
+// match gestures.wait_for_event(&button) {
+//     ButtonEvent::Click => arm(),
+//     ButtonEvent::LongPress(_) => disarm(),
+//     _ => {}
+// }
+// ```
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::AtomicBool,
+    time::Duration,
+};
+use crate::{
+    clock::{ Clock, SystemClock },
+    debounce::{ ButtonEdge, DebounceConfig, Debouncer },
+    digital::DigitalInput,
+    wait::WaitLimit,
+};
+
+/// Recognized gesture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Click,
+    DoubleClick,
+    /// Released after a long hold, with how long it was held.
+    LongPress(Duration),
+    HoldRepeat,
+}
+
+/// Gesture thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// Max gap between the first release and the second press of a double click.
+    pub double_click_window: Duration,
+    /// Min hold time of a long press.
+    pub long_press_time: Duration,
+    /// Hold time before the first `HoldRepeat`.
+    pub hold_repeat_delay: Duration,
+    /// Gap between the following `HoldRepeat`s.
+    pub hold_repeat_interval: Duration,
+}
+
+impl Default for GestureConfig {
+    /// `250ms` double click window, `1s` long press, `HoldRepeat` after `1s` and then every `250ms`.
+    fn default() -> Self {
+        GestureConfig {
+            double_click_window: Duration::from_millis(250),
+            long_press_time: Duration::from_secs(1),
+            hold_repeat_delay: Duration::from_secs(1),
+            hold_repeat_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Gesture state machine fed with debounced edges.
+#[derive(Debug, Clone)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    // When the current press started, `None` when released.
+    pressed_at: Option<Duration>,
+    // `HoldRepeat`s sent during the current press.
+    repeats_sent: u32,
+    // A short press is waiting for the double click window to end.
+    pending_click_until: Option<Duration>,
+    // The current press is the second one of a possible double click.
+    second_press: bool,
+    events: VecDeque<ButtonEvent>,
+}
+
+impl GestureRecognizer {
+    /// Create a recognizer with the button released.
+    pub fn new(config: GestureConfig) -> Self {
+        GestureRecognizer {
+            config,
+            pressed_at: None,
+            repeats_sent: 0,
+            pending_click_until: None,
+            second_press: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Current settings.
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// Feed the debounced edge (if any) seen at `now`. Call it regularly even
+    /// without edges, clicks and hold repeats are time based.
+    pub fn update(&mut self, edge: Option<ButtonEdge>, now: Duration) -> Option<ButtonEvent> {
+        match edge {
+            Some(ButtonEdge::Pressed) => self.on_pressed(now),
+            Some(ButtonEdge::Released) => self.on_released(now),
+            None => {}
+        }
+
+        self.on_tick(now);
+        self.events.pop_front()
+    }
+
+    fn on_pressed(&mut self, now: Duration) {
+        self.pressed_at = Some(now);
+        self.repeats_sent = 0;
+        self.second_press = self.pending_click_until.take().is_some();
+    }
+
+    fn on_released(&mut self, now: Duration) {
+        let pressed_at = match self.pressed_at.take() {
+            Some(pressed_at) => pressed_at,
+            None => return,
+        };
+
+        let held = now.checked_sub(pressed_at).unwrap_or_default();
+        if held >= self.config.long_press_time {
+            self.events.push_back(ButtonEvent::LongPress(held));
+        } else if self.second_press {
+            self.events.push_back(ButtonEvent::DoubleClick);
+        } else if self.config.double_click_window == Duration::from_millis(0) {
+            self.events.push_back(ButtonEvent::Click);
+        } else {
+            self.pending_click_until = Some(now + self.config.double_click_window);
+        }
+
+        self.second_press = false;
+    }
+
+    fn on_tick(&mut self, now: Duration) {
+        if let Some(until) = self.pending_click_until {
+            if now >= until {
+                self.pending_click_until = None;
+                self.events.push_back(ButtonEvent::Click);
+            }
+        }
+
+        if let Some(pressed_at) = self.pressed_at {
+            let held = now.checked_sub(pressed_at).unwrap_or_default();
+
+            // The second press turned into a long one, the first press was just a click
+            if self.second_press && held >= self.config.long_press_time {
+                self.second_press = false;
+                self.events.push_back(ButtonEvent::Click);
+            }
+
+            let next_repeat = self.config.hold_repeat_delay + self.config.hold_repeat_interval * self.repeats_sent;
+            if held >= next_repeat {
+                self.repeats_sent += 1;
+                self.events.push_back(ButtonEvent::HoldRepeat);
+            }
+        }
+    }
+}
+
+/// `Debouncer` + `GestureRecognizer` reading straight from a pin.
+#[derive(Debug, Clone)]
+pub struct GestureDetector {
+    debouncer: Debouncer,
+    recognizer: GestureRecognizer,
+}
+
+impl GestureDetector {
+    /// Create a detector.
+    pub fn new(debounce_config: DebounceConfig, gesture_config: GestureConfig) -> Self {
+        GestureDetector {
+            debouncer: Debouncer::new(debounce_config),
+            recognizer: GestureRecognizer::new(gesture_config),
+        }
+    }
+
+    /// Non-blocking: sample `pin` once at `clock.now()`.
+    pub fn poll<P, C>(&mut self, pin: &P, clock: &C) -> Option<ButtonEvent>
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        let now = clock.now();
+        let edge = self.debouncer.update(self.debouncer.reads_pressed(pin), now);
+        self.recognizer.update(edge, now)
+    }
+
+    /// Blocking: return the next gesture.
+    pub fn wait_for_event<P: DigitalInput + ?Sized>(&mut self, pin: &P) -> ButtonEvent {
+        loop {
+            if let Some(event) = self.wait_for_event_with_limit(pin, WaitLimit::forever(), &SystemClock::new()) {
+                return event;
+            }
+        }
+    }
+
+    /// Same with `wait_for_event`, but give up as soon as `cancel` becomes `true`.
+    pub fn wait_for_event_or_cancelled<P: DigitalInput + ?Sized>(&mut self, pin: &P, cancel: &AtomicBool) -> Option<ButtonEvent> {
+        self.wait_for_event_with_limit(pin, WaitLimit::cancelled_by(cancel), &SystemClock::new())
+    }
+
+    /// The general form of `wait_for_event`: stop waiting when `limit` is
+    /// reached, sampling time goes through the given `Clock`.
+    pub fn wait_for_event_with_limit<P, C>(&mut self, pin: &P, limit: WaitLimit, clock: &C) -> Option<ButtonEvent>
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        let started = clock.now();
+        loop {
+            if let Some(event) = self.poll(pin, clock) {
+                return Some(event);
+            }
+
+            if limit.is_reached(started, clock.now()) {
+                return None;
+            }
+
+            clock.sleep(self.debouncer.config().sample_period)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{ InputScript, MockClock, MockGpio };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Run the detector over `script` for `total` virtual time, collect events with timestamps.
+    fn events_of(script: InputScript, total: Duration) -> Vec<(ButtonEvent, Duration)> {
+        let gpio = MockGpio::new();
+        events_on(&gpio.input_pin(17, script), &gpio.clock(), total)
+    }
+
+    // Same on any input, e.g. a `ConfiguredInput` around the mock pin.
+    fn events_on<P: DigitalInput>(button: &P, clock: &MockClock, total: Duration) -> Vec<(ButtonEvent, Duration)> {
+        let mut gestures = GestureDetector::new(DebounceConfig::default(), GestureConfig::default());
+
+        let mut events = Vec::new();
+        while clock.now() < total {
+            if let Some(event) = gestures.poll(button, clock) {
+                events.push((event, clock.now()));
+            }
+            clock.sleep(ms(1));
+        }
+        events
+    }
+
+    #[test]
+    fn active_high_buttons_should_give_the_same_gestures() {
+        use crate::{ ConfiguredInput, InputConfig };
+
+        // The click below, on a switch to 3.3V: pressed is `HIGH`
+        let gpio = MockGpio::new();
+        let script = InputScript::new().low_for(ms(100)).high_for(ms(100)).low();
+        let button = ConfiguredInput::new(gpio.input_pin(17, script), InputConfig::switch_to_3v3().active_level);
+
+        assert_eq!(events_on(&button, &gpio.clock(), ms(1000)), vec![(ButtonEvent::Click, ms(470))]);
+    }
+
+    #[test]
+    fn short_press_should_be_a_click_after_the_double_click_window() {
+        let script = InputScript::new().high_for(ms(100)).low_for(ms(100)).high();
+
+        // Released at 220ms (debounced), clicked when the window ends
+        assert_eq!(events_of(script, ms(1000)), vec![(ButtonEvent::Click, ms(470))]);
+    }
+
+    #[test]
+    fn two_quick_presses_should_be_a_double_click() {
+        let script = InputScript::new()
+            .high_for(ms(100)).low_for(ms(80))
+            .high_for(ms(100)).low_for(ms(80))
+            .high();
+
+        assert_eq!(events_of(script, ms(1000)), vec![(ButtonEvent::DoubleClick, ms(380))]);
+    }
+
+    #[test]
+    fn long_hold_should_repeat_and_end_with_a_long_press() {
+        let script = InputScript::new().high_for(ms(100)).low_for(ms(1600)).high();
+
+        // Pressed at 120ms, released at 1720ms
+        assert_eq!(events_of(script, ms(2000)), vec![
+            (ButtonEvent::HoldRepeat, ms(1120)),
+            (ButtonEvent::HoldRepeat, ms(1370)),
+            (ButtonEvent::HoldRepeat, ms(1620)),
+            (ButtonEvent::LongPress(ms(1600)), ms(1720)),
+        ]);
+    }
+
+    #[test]
+    fn zero_double_click_window_should_click_on_release() {
+        let mut recognizer = GestureRecognizer::new(GestureConfig {
+            double_click_window: ms(0),
+            ..GestureConfig::default()
+        });
+
+        assert_eq!(recognizer.update(Some(ButtonEdge::Pressed), ms(0)), None);
+        assert_eq!(recognizer.update(Some(ButtonEdge::Released), ms(100)), Some(ButtonEvent::Click));
+    }
+}
@@ -0,0 +1,157 @@
+// Input pin configuration: internal pull resistor and polarity.
+//
+// The button modules in the demos have their own pull-up, a bare tactile
+// switch doesn't, and its input floats unless the internal pull-up/down is
+// on. `ConfiguredInput` also remembers which level means pressed, so
+// `is_pressed()` and `GpioUtil::block_until_button_pressed` work the same
+// whatever the wiring:
+//
+// ```rust
+// // Bare switch between the pin and GND
+// let button = GpioUtil::create_configured_input_pin(17, InputConfig::switch_to_ground())?;
+//
+// // Bare switch between the pin and 3.3V
+// let button = GpioUtil::create_configured_input_pin(17, InputConfig::switch_to_3v3())?;
+//
+// if button.is_pressed() { ... }
+// GpioUtil::block_until_button_pressed(&button);
+// ```
+
+use rppal::gpio::Level;
+use std::{ sync::mpsc::Receiver, time::Duration };
+use crate::{
+    digital::{ ActiveLevel, DigitalInput },
+    edge::{ Edge, EdgeInput },
+    error::Result,
+};
+
+/// Internal pull resistor of an input pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pull {
+    /// Floating, for modules which have their own pull resistor.
+    None,
+    /// Pulled `HIGH`.
+    Up,
+    /// Pulled `LOW`.
+    Down,
+}
+
+/// How to set up an input pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputConfig {
+    /// Internal pull resistor.
+    pub pull: Pull,
+    /// Which level means pressed.
+    pub active_level: ActiveLevel,
+}
+
+impl InputConfig {
+    /// Switch between the pin and GND: pull-up, active `LOW`.
+    pub fn switch_to_ground() -> Self {
+        InputConfig { pull: Pull::Up, active_level: ActiveLevel::Low }
+    }
+
+    /// Switch between the pin and 3.3V: pull-down, active `HIGH`.
+    pub fn switch_to_3v3() -> Self {
+        InputConfig { pull: Pull::Down, active_level: ActiveLevel::High }
+    }
+}
+
+impl Default for InputConfig {
+    /// No pull resistor, active `LOW`, same with `create_input_pin`.
+    fn default() -> Self {
+        InputConfig { pull: Pull::None, active_level: ActiveLevel::Low }
+    }
+}
+
+/// An input pin which knows its active level.
+#[derive(Debug)]
+pub struct ConfiguredInput<P> {
+    pin: P,
+    active_level: ActiveLevel,
+}
+
+impl<P> ConfiguredInput<P> {
+    /// Wrap an already created `pin`, e.g. a `MemoryPin` in tests.
+    pub fn new(pin: P, active_level: ActiveLevel) -> Self {
+        ConfiguredInput { pin, active_level }
+    }
+
+    /// The wrapped pin.
+    pub fn pin(&self) -> &P {
+        &self.pin
+    }
+
+    /// Give the wrapped pin back.
+    pub fn into_inner(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: DigitalInput> DigitalInput for ConfiguredInput<P> {
+    fn read(&self) -> Level {
+        self.pin.read()
+    }
+
+    fn active_level(&self) -> ActiveLevel {
+        self.active_level
+    }
+}
+
+impl<P: EdgeInput> EdgeInput for ConfiguredInput<P> {
+    fn subscribe<F>(&mut self, edge: Edge, callback: F) -> Result<()>
+    where
+        F: FnMut(Level) + Send + 'static,
+    {
+        self.pin.subscribe(edge, callback)
+    }
+
+    fn unsubscribe(&mut self) -> Result<()> {
+        self.pin.unsubscribe()
+    }
+
+    fn subscribe_channel(&mut self, edge: Edge) -> Result<Receiver<Level>> {
+        self.pin.subscribe_channel(edge)
+    }
+
+    fn wait_for_edge(&mut self, edge: Edge, timeout: Option<Duration>) -> Result<Option<Level>> {
+        self.pin.wait_for_edge(edge, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{ Clock, GpioUtil, InputScript, MemoryPin, MockGpio };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn is_pressed_should_follow_the_active_level() {
+        let button = MemoryPin::new(Level::High);
+        let active_low = ConfiguredInput::new(button.clone(), ActiveLevel::Low);
+        let active_high = ConfiguredInput::new(button.clone(), ActiveLevel::High);
+
+        assert_eq!(active_low.is_pressed(), false);
+        assert_eq!(active_high.is_pressed(), true);
+
+        button.set_level(Level::Low);
+        assert_eq!(active_low.is_pressed(), true);
+        assert_eq!(active_high.is_pressed(), false);
+    }
+
+    #[test]
+    fn block_until_button_pressed_should_work_with_active_high_buttons() {
+        // Switch to 3.3V with pull-down: idle `LOW`, pressed `HIGH`
+        let gpio = MockGpio::new();
+        let script = InputScript::new().low_for(ms(100)).high_for(ms(50)).low();
+        let button = ConfiguredInput::new(gpio.input_pin(17, script), InputConfig::switch_to_3v3().active_level);
+
+        GpioUtil::block_until_button_pressed_with_clock(&button, &gpio.clock());
+        assert_eq!(gpio.clock().now() >= ms(150), true);
+        assert_eq!(gpio.clock().now() < ms(170), true);
+    }
+}
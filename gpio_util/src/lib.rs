@@ -11,12 +11,68 @@
 // ```bash
 // sudo -E /home/ubuntu/.cargo/bin/cargo test -- --nocapture
 // ```
+//
+// The tests which need the real hardware are marked `#[ignore]`, run them with:
+//
+// ```bash
+// sudo -E /home/ubuntu/.cargo/bin/cargo test -- --ignored --nocapture
+// ```
+//
+// Everything else works against the `DigitalInput`/`DigitalOutput` traits, so
+// it can be tested on any machine with `MemoryPin`, or with the scripted
+// `MockGpio` backend when timing matters.
 
-use rppal::gpio::{ Gpio, InputPin, OutputPin, Level, Result };
-use std::{ thread, time::Duration };
+#[cfg(feature = "async")]
+mod async_pin;
+mod blink;
+mod buzzer;
+mod clock;
+mod debounce;
+mod digital;
+mod edge;
+mod error;
+mod gesture;
+mod input;
+mod memory;
+mod mock;
+mod morse;
+mod pin_id;
+mod registry;
+mod safe_state;
+mod scheduler;
+mod soft_pwm;
+mod wait;
+#[cfg(feature = "wiring")]
+mod wiring;
+
+#[cfg(feature = "async")]
+pub use async_pin::{ edge_stream, wait_for_edge, wait_for_press, EdgeStream };
+pub use blink::{ BlinkPattern, PatternPlayer, Repeat };
+pub use buzzer::{ Melody, MelodyError, Note, ToneOutput, TonePlayer };
+pub use clock::{ Clock, SystemClock };
+pub use debounce::{ ButtonEdge, DebounceConfig, Debouncer };
+pub use digital::{ ActiveLevel, DigitalInput, DigitalOutput };
+pub use edge::{ Edge, EdgeInput, PollingInput };
+pub use error::{ GpioError, Result };
+pub use gesture::{ ButtonEvent, GestureConfig, GestureDetector, GestureRecognizer };
+pub use input::{ ConfiguredInput, InputConfig, Pull };
+pub use memory::MemoryPin;
+pub use mock::{ InputScript, MockClock, MockGpio, MockInputPin, MockOutputPin, Transition };
+pub use morse::{ Morse, MorseDecoder, MorseTiming };
+pub use pin_id::{ AltFunction, PinId };
+pub use registry::{ ClaimedPin, PinAllocation, PinClaim, PinMode, PinRegistry };
+pub use safe_state::{ SafeOutput, SafeState };
+pub use scheduler::{ OutputScheduler, ScheduleHandle, ScheduledPin };
+pub use soft_pwm::{ PwmTiming, SoftPwm, SOFT_PWM_MAX_FREQUENCY };
+pub use wait::WaitLimit;
+#[cfg(feature = "wiring")]
+pub use wiring::{ Device, I2cAddress, Wiring };
+
+use rppal::gpio::{ Gpio, InputPin, OutputPin, Level, Mode, Pin };
+use std::{ sync::atomic::AtomicBool, time::Duration };
 
 ///
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum  PinState {
     Low = 0,
     High = 1,
@@ -28,55 +84,150 @@ pub struct GpioUtil {}
 
 impl GpioUtil {
     
-    /// Create `InputPin` instance. Return `Error::PinNotAvailable` if fail.
+    /// Create `InputPin` instance. Return `GpioError::PinInUse` or
+    /// `GpioError::PinReserved` if the pin can't be used.
     pub fn create_input_pin(gpio_pin_number: u8) -> Result<InputPin> {
-        // `Gpio::new` get back a unconfigured `Gpio` instance
-        // `Gpio::get` get back the `BCM GPIO` `Pin` instance. The pin number is `BCM GPIO` pin
-        // number!!!
-        Ok(Gpio::new()?.get(gpio_pin_number)?.into_input())
+        Ok(GpioUtil::get_free_pin(gpio_pin_number)?.into_input())
     }
 
-    /// Create `OutputPin` instance. Return `Error::PinNotAvailable` if fail.
+    /// Create `OutputPin` instance. Return `GpioError::PinInUse` or
+    /// `GpioError::PinReserved` if the pin can't be used.
     pub fn create_output_pin(gpio_pin_number: u8) -> Result<OutputPin> {
+        Ok(GpioUtil::get_free_pin(gpio_pin_number)?.into_output())
+    }
+
+    /// Create an input pin with the internal pull resistor and active level
+    /// of `config`, e.g. `InputConfig::switch_to_ground()` for a bare switch.
+    pub fn create_configured_input_pin(gpio_pin_number: u8, config: InputConfig) -> Result<ConfiguredInput<InputPin>> {
+        let pin = GpioUtil::get_free_pin(gpio_pin_number)?;
+        let input_pin = match config.pull {
+            Pull::None => pin.into_input(),
+            Pull::Up => pin.into_input_pullup(),
+            Pull::Down => pin.into_input_pulldown(),
+        };
+        Ok(ConfiguredInput::new(input_pin, config.active_level))
+    }
+
+    // Get the pin, unless an overlay switched it to an alternate function.
+    fn get_free_pin(gpio_pin_number: u8) -> Result<Pin> {
         // `Gpio::new` get back a unconfigured `Gpio` instance
         // `Gpio::get` get back the `BCM GPIO` `Pin` instance. The pin number is `BCM GPIO` pin
         // number!!!
-        Ok(Gpio::new()?.get(gpio_pin_number)?.into_output())
+        let pin = Gpio::new()?.get(gpio_pin_number)?;
+        match pin.mode() {
+            Mode::Input | Mode::Output => Ok(pin),
+            mode => Err(GpioError::PinReserved { pin: gpio_pin_number, mode }),
+        }
+    }
+
+    /// Set pin state. The delay blocks the caller, use `OutputScheduler` to
+    /// drive pins with independent timing from one thread.
+    pub fn set_pin_state<P: DigitalOutput + ?Sized>(pin: &mut P, init_state: PinState, delay_after_reset: Option<Duration>) {
+        GpioUtil::set_pin_state_with_clock(pin, init_state, delay_after_reset, &SystemClock::new())
     }
 
-    /// Set pin state
-    pub fn set_pin_state(pin: &mut OutputPin, init_state: PinState, delay_after_reset: Option<Duration>) {
+    /// Same with `set_pin_state`, but the delay goes through the given `Clock`.
+    pub fn set_pin_state_with_clock<P, C>(pin: &mut P, init_state: PinState, delay_after_reset: Option<Duration>, clock: &C)
+    where
+        P: DigitalOutput + ?Sized,
+        C: Clock + ?Sized,
+    {
         match init_state {
             PinState::Low => pin.set_low(),
             PinState::High => pin.set_high()
         }
 
-        if delay_after_reset.is_some() {
-            thread::sleep(delay_after_reset.unwrap())
+        if let Some(delay) = delay_after_reset {
+            clock.sleep(delay)
         }
     }
 
     /// Keep reading state from button pin, only return when button pressed
-    /// and then released (`HIGH->LOW->HIGH`) happens.
-    pub fn block_until_button_pressed(button_pin: &InputPin) {
-        let mut init_high = false;
+    /// and then released (`HIGH->LOW->HIGH`) happens. "Pressed" is the pin's
+    /// `active_level`, so for an active `HIGH` `ConfiguredInput` it's `LOW->HIGH->LOW`.
+    ///
+    /// A single `LOW` sample counts as pressed, use `Debouncer::wait_for_press`
+    /// for noisy buttons.
+    pub fn block_until_button_pressed<P: DigitalInput + ?Sized>(button_pin: &P) {
+        GpioUtil::block_until_button_pressed_with_clock(button_pin, &SystemClock::new())
+    }
+
+    /// Same with `block_until_button_pressed`, but give up after `timeout`.
+    /// Return `true` if the button was pressed.
+    pub fn block_until_button_pressed_timeout<P: DigitalInput + ?Sized>(button_pin: &P, timeout: Duration) -> bool {
+        GpioUtil::block_until_button_pressed_with_limit(button_pin, WaitLimit::timeout(timeout), &SystemClock::new())
+    }
+
+    /// Same with `block_until_button_pressed`, but give up as soon as `cancel`
+    /// becomes `true`. Return `true` if the button was pressed.
+    pub fn block_until_button_pressed_or_cancelled<P: DigitalInput + ?Sized>(button_pin: &P, cancel: &AtomicBool) -> bool {
+        GpioUtil::block_until_button_pressed_with_limit(button_pin, WaitLimit::cancelled_by(cancel), &SystemClock::new())
+    }
+
+    /// Same with `block_until_button_pressed`, but sampling time goes through
+    /// the given `Clock`.
+    pub fn block_until_button_pressed_with_clock<P, C>(button_pin: &P, clock: &C)
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        GpioUtil::block_until_button_pressed_with_limit(button_pin, WaitLimit::forever(), clock);
+    }
+
+    /// The general form of `block_until_button_pressed`: stop waiting when
+    /// `limit` is reached, sampling time goes through the given `Clock`.
+    /// Return `true` if the button was pressed.
+    pub fn block_until_button_pressed_with_limit<P, C>(button_pin: &P, limit: WaitLimit, clock: &C) -> bool
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        let mut init_released = false;
         let mut pressed = false;
-        let mut released = false;
         let button_read_period = Duration::from_millis(10);
+        let started = clock.now();
         loop {
-            let temp_button_state = button_pin.read();
+            let temp_button_pressed = button_pin.is_pressed();
 
-            // init high
-            if temp_button_state == Level::High && init_high == false { init_high = true; }
+            // init released
+            if !temp_button_pressed && !init_released { init_released = true; }
             // Detect pressed
-            else if temp_button_state == Level::Low && init_high == true { pressed = true; }
+            else if temp_button_pressed && init_released { pressed = true; }
             // Detect released after pressing
-            else if temp_button_state == Level::High && init_high == true && pressed == true {
-                released = true;
-                return;
+            else if !temp_button_pressed && init_released && pressed {
+                return true;
+            }
+
+            if limit.is_reached(started, clock.now()) {
+                return false;
             }
 
-            thread::sleep(button_read_period)
+            clock.sleep(button_read_period)
+        }
+    }
+
+    /// Polling version of `EdgeInput::wait_for_edge` for pins without
+    /// interrupts, e.g. the scripted `MockGpio` pins on virtual time.
+    /// Return the new level, or `None` when `limit` is reached first.
+    pub fn wait_for_edge_with_clock<P, C>(pin: &P, edge: Edge, poll_period: Duration, limit: WaitLimit, clock: &C) -> Option<Level>
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        let started = clock.now();
+        let mut last_level = pin.read();
+        loop {
+            if limit.is_reached(started, clock.now()) {
+                return None;
+            }
+
+            clock.sleep(poll_period);
+
+            let level = pin.read();
+            if level != last_level && edge.matches(level) {
+                return Some(level);
+            }
+            last_level = level;
         }
     }
 }
@@ -86,12 +237,14 @@ impl GpioUtil {
 mod tests {
 
     use super::*;
+    use std::thread;
 
     #[test]
+    #[ignore]
     fn create_input_pin_should_fail_without_sudo_permission() {
         let input_pin = GpioUtil::create_input_pin(8);
 
-        // It should print out `Error(PermissionDenied("/dev/gpiomem"))`
+        // It should print out `Err(permission denied on `/dev/gpiomem`, ...)`
         // if you run without `sudo`!!!
         // println!("input_pin {:?}", input_pin);
 
@@ -99,6 +252,7 @@ mod tests {
     }
 
     #[test]
+    #[ignore]
     fn set_pin_state_should_work() {
         let mut output_pin = GpioUtil::create_output_pin(25).unwrap();
         println!("output_pin {:#?}", output_pin);
@@ -111,4 +265,115 @@ mod tests {
         assert_eq!(output_pin.is_set_low(), true);
     }
 
+    #[test]
+    fn set_pin_state_should_work_with_memory_pin() {
+        let mut output_pin = MemoryPin::new(Level::Low);
+
+        GpioUtil::set_pin_state(&mut output_pin, PinState::High, None);
+        assert_eq!(output_pin.is_set_high(), true);
+
+        GpioUtil::set_pin_state(&mut output_pin, PinState::Low, None);
+        assert_eq!(output_pin.is_set_low(), true);
+    }
+
+    #[test]
+    fn block_until_button_pressed_should_return_after_release() {
+        let button = MemoryPin::new(Level::High);
+        let finger = button.clone();
+
+        let presser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            finger.set_level(Level::Low);
+            thread::sleep(Duration::from_millis(50));
+            finger.set_level(Level::High);
+        });
+
+        GpioUtil::block_until_button_pressed(&button);
+        assert_eq!(button.is_high(), true);
+        presser.join().unwrap();
+    }
+
+    #[test]
+    fn block_until_button_pressed_should_return_on_release_edge() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let script = InputScript::new()
+            .high_for(Duration::from_millis(30))
+            .low_for(Duration::from_millis(80))
+            .high();
+        let button = gpio.input_pin(17, script);
+
+        GpioUtil::block_until_button_pressed_with_clock(&button, &clock);
+        assert_eq!(clock.now(), Duration::from_millis(110));
+    }
+
+    #[test]
+    fn block_until_button_pressed_should_ignore_button_held_at_startup() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        // Held at startup, released at 50ms, then a real press from 100ms to 150ms
+        let script = InputScript::new()
+            .low_for(Duration::from_millis(50))
+            .high_for(Duration::from_millis(50))
+            .low_for(Duration::from_millis(50))
+            .high();
+        let button = gpio.input_pin(17, script);
+
+        GpioUtil::block_until_button_pressed_with_clock(&button, &clock);
+        assert_eq!(clock.now(), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn block_until_button_pressed_with_limit_should_time_out() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let button = gpio.input_pin(17, InputScript::new().high());
+
+        let limit = WaitLimit::timeout(Duration::from_millis(500));
+        assert_eq!(GpioUtil::block_until_button_pressed_with_limit(&button, limit, &clock), false);
+        assert_eq!(clock.now(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn block_until_button_pressed_or_cancelled_should_stop_when_cancelled() {
+        let button = MemoryPin::new(Level::High);
+        let cancel = std::sync::Arc::new(AtomicBool::new(false));
+        let canceller = cancel.clone();
+
+        let waiter = thread::spawn(move || GpioUtil::block_until_button_pressed_or_cancelled(&button, &cancel));
+        thread::sleep(Duration::from_millis(50));
+        canceller.store(true, std::sync::atomic::Ordering::SeqCst);
+
+        assert_eq!(waiter.join().unwrap(), false);
+    }
+
+    #[test]
+    fn wait_for_edge_with_clock_should_return_on_the_matching_edge() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let script = InputScript::new()
+            .high_for(Duration::from_millis(30))
+            .low_for(Duration::from_millis(5))
+            .high();
+        let button = gpio.input_pin(17, script);
+
+        let level = GpioUtil::wait_for_edge_with_clock(&button, Edge::Rising, Duration::from_millis(1), WaitLimit::forever(), &clock);
+        assert_eq!(level, Some(Level::High));
+        assert_eq!(clock.now(), Duration::from_millis(35));
+    }
+
+    #[test]
+    fn set_pin_state_with_clock_should_record_delays() {
+        let gpio = MockGpio::new();
+        let mut led = gpio.output_pin(18, Level::Low);
+
+        GpioUtil::set_pin_state_with_clock(&mut led, PinState::High, Some(Duration::from_secs(1)), &gpio.clock());
+        GpioUtil::set_pin_state_with_clock(&mut led, PinState::Low, Some(Duration::from_secs(1)), &gpio.clock());
+
+        assert_eq!(gpio.transitions(18), vec![
+            Transition { at: Duration::from_secs(0), level: Level::High },
+            Transition { at: Duration::from_secs(1), level: Level::Low },
+        ]);
+        assert_eq!(gpio.clock().now(), Duration::from_secs(2));
+    }
 }
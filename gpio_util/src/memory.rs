@@ -0,0 +1,80 @@
+// In-memory GPIO backend.
+//
+// `MemoryPin` doesn't touch any hardware, so it works without `/dev/gpiomem`
+// and `sudo`. Clones share the same level, which means a test can keep one
+// clone to drive (or inspect) the pin while the code under test owns another.
+
+use rppal::gpio::Level;
+use std::sync::{
+    atomic::{ AtomicBool, Ordering },
+    Arc,
+};
+use crate::digital::{ DigitalInput, DigitalOutput };
+
+/// A pin that only lives in memory, usable as both input and output.
+#[derive(Debug, Clone)]
+pub struct MemoryPin {
+    high: Arc<AtomicBool>,
+}
+
+impl MemoryPin {
+    /// Create a pin with the given initial level.
+    pub fn new(level: Level) -> Self {
+        MemoryPin { high: Arc::new(AtomicBool::new(level == Level::High)) }
+    }
+
+    /// Set the level seen by every clone. Useful for simulating an input
+    /// through a shared (non `mut`) handle.
+    pub fn set_level(&self, level: Level) {
+        self.high.store(level == Level::High, Ordering::SeqCst);
+    }
+
+    /// Current level.
+    pub fn level(&self) -> Level {
+        if self.high.load(Ordering::SeqCst) { Level::High } else { Level::Low }
+    }
+}
+
+impl Default for MemoryPin {
+    fn default() -> Self {
+        MemoryPin::new(Level::Low)
+    }
+}
+
+impl DigitalInput for MemoryPin {
+    fn read(&self) -> Level {
+        self.level()
+    }
+}
+
+impl DigitalOutput for MemoryPin {
+    fn set_high(&mut self) {
+        self.set_level(Level::High)
+    }
+
+    fn set_low(&mut self) {
+        self.set_level(Level::Low)
+    }
+
+    fn is_set_high(&self) -> bool {
+        self.level() == Level::High
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn clones_should_share_the_same_level() {
+        let mut output = MemoryPin::new(Level::Low);
+        let observer = output.clone();
+
+        output.set_high();
+        assert_eq!(observer.is_high(), true);
+
+        output.toggle();
+        assert_eq!(observer.is_low(), true);
+    }
+}
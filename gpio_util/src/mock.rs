@@ -0,0 +1,273 @@
+// Scriptable mock GPIO backend running on virtual time.
+//
+// ```rust
+// let gpio = MockGpio::new();
+//
+// // Pin 17: `HIGH` for 30ms, `LOW` for 80ms, then `HIGH` forever.
+// let button = gpio.input_pin(17, InputScript::new().high_for(ms(30)).low_for(ms(80)).high());
+// let mut laser = gpio.output_pin(27, Level::Low);
+//
+// GpioUtil::block_until_button_pressed_with_clock(&button, &gpio.clock());
+// assert_eq!(gpio.clock().now(), ms(110));
+//
+// laser.set_high();
+// assert_eq!(gpio.transitions(27), vec![Transition { at: ms(110), level: Level::High }]);
+// ```
+//
+// Every `sleep` on the `MockClock` returns immediately and only moves the
+// virtual time forward, so the tests are fast and deterministic.
+
+use rppal::gpio::Level;
+use std::{
+    collections::HashMap,
+    sync::{ Arc, Mutex },
+    time::Duration,
+};
+use crate::{
+    clock::Clock,
+    digital::{ DigitalInput, DigitalOutput },
+};
+
+/// Virtual clock, clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    /// Create a clock at virtual time `0`.
+    pub fn new() -> Self {
+        MockClock::default()
+    }
+
+    /// Move the virtual time forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+/// Input levels over virtual time, built step by step. After the last step
+/// the pin stays at the final level forever.
+#[derive(Debug, Clone)]
+pub struct InputScript {
+    steps: Vec<(Level, Duration)>,
+    last: Level,
+}
+
+impl InputScript {
+    /// Empty script, the pin reads `HIGH` until told otherwise.
+    pub fn new() -> Self {
+        InputScript { steps: Vec::new(), last: Level::High }
+    }
+
+    /// Hold `level` for `duration`.
+    pub fn level_for(mut self, level: Level, duration: Duration) -> Self {
+        self.steps.push((level, duration));
+        self.last = level;
+        self
+    }
+
+    /// Hold `HIGH` for `duration`.
+    pub fn high_for(self, duration: Duration) -> Self {
+        self.level_for(Level::High, duration)
+    }
+
+    /// Hold `LOW` for `duration`.
+    pub fn low_for(self, duration: Duration) -> Self {
+        self.level_for(Level::Low, duration)
+    }
+
+    /// Stay `HIGH` after the previous steps.
+    pub fn high(mut self) -> Self {
+        self.last = Level::High;
+        self
+    }
+
+    /// Stay `LOW` after the previous steps.
+    pub fn low(mut self) -> Self {
+        self.last = Level::Low;
+        self
+    }
+
+    /// Level at the given virtual time.
+    pub fn level_at(&self, at: Duration) -> Level {
+        let mut step_end = Duration::from_millis(0);
+        for (level, duration) in self.steps.iter() {
+            step_end += *duration;
+            if at < step_end {
+                return *level;
+            }
+        }
+
+        self.last
+    }
+}
+
+impl Default for InputScript {
+    fn default() -> Self {
+        InputScript::new()
+    }
+}
+
+/// One recorded output level change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    /// Virtual time when the change happened.
+    pub at: Duration,
+    /// New level.
+    pub level: Level,
+}
+
+type TransitionLog = Arc<Mutex<Vec<Transition>>>;
+
+/// Mock board which hands out scripted inputs and recording outputs sharing
+/// one `MockClock`.
+#[derive(Debug, Clone, Default)]
+pub struct MockGpio {
+    clock: MockClock,
+    outputs: Arc<Mutex<HashMap<u8, TransitionLog>>>,
+}
+
+impl MockGpio {
+    /// Create a mock board at virtual time `0`.
+    pub fn new() -> Self {
+        MockGpio::default()
+    }
+
+    /// The shared virtual clock, pass it to the `*_with_clock` helpers.
+    pub fn clock(&self) -> MockClock {
+        self.clock.clone()
+    }
+
+    /// Create an input pin which follows `script`.
+    pub fn input_pin(&self, gpio_pin_number: u8, script: InputScript) -> MockInputPin {
+        MockInputPin { pin: gpio_pin_number, clock: self.clock(), script }
+    }
+
+    /// Create an output pin which records every level change.
+    pub fn output_pin(&self, gpio_pin_number: u8, init_level: Level) -> MockOutputPin {
+        let log = self.outputs.lock().unwrap()
+            .entry(gpio_pin_number)
+            .or_insert_with(|| Arc::new(Mutex::new(Vec::new())))
+            .clone();
+
+        MockOutputPin { pin: gpio_pin_number, clock: self.clock(), level: init_level, log }
+    }
+
+    /// All level changes recorded on the given output pin so far.
+    pub fn transitions(&self, gpio_pin_number: u8) -> Vec<Transition> {
+        match self.outputs.lock().unwrap().get(&gpio_pin_number) {
+            Some(log) => log.lock().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Input pin driven by an `InputScript`.
+#[derive(Debug, Clone)]
+pub struct MockInputPin {
+    pin: u8,
+    clock: MockClock,
+    script: InputScript,
+}
+
+impl MockInputPin {
+    /// BCM pin number.
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+}
+
+impl DigitalInput for MockInputPin {
+    fn read(&self) -> Level {
+        self.script.level_at(self.clock.now())
+    }
+}
+
+/// Output pin which records its level changes with virtual timestamps.
+#[derive(Debug)]
+pub struct MockOutputPin {
+    pin: u8,
+    clock: MockClock,
+    level: Level,
+    log: TransitionLog,
+}
+
+impl MockOutputPin {
+    /// BCM pin number.
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    fn change_to(&mut self, level: Level) {
+        if self.level != level {
+            self.level = level;
+            self.log.lock().unwrap().push(Transition { at: self.clock.now(), level });
+        }
+    }
+}
+
+impl DigitalOutput for MockOutputPin {
+    fn set_high(&mut self) {
+        self.change_to(Level::High)
+    }
+
+    fn set_low(&mut self) {
+        self.change_to(Level::Low)
+    }
+
+    fn is_set_high(&self) -> bool {
+        self.level == Level::High
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn input_script_should_follow_the_timeline() {
+        let script = InputScript::new().high_for(ms(30)).low_for(ms(80)).high();
+
+        assert_eq!(script.level_at(ms(0)), Level::High);
+        assert_eq!(script.level_at(ms(29)), Level::High);
+        assert_eq!(script.level_at(ms(30)), Level::Low);
+        assert_eq!(script.level_at(ms(109)), Level::Low);
+        assert_eq!(script.level_at(ms(110)), Level::High);
+        assert_eq!(script.level_at(ms(10_000)), Level::High);
+    }
+
+    #[test]
+    fn output_pin_should_record_transitions() {
+        let gpio = MockGpio::new();
+        let clock = gpio.clock();
+        let mut laser = gpio.output_pin(27, Level::Low);
+
+        // Not a transition, already `LOW`
+        laser.set_low();
+        clock.sleep(ms(5));
+        laser.set_high();
+        clock.sleep(ms(20));
+        laser.toggle();
+
+        assert_eq!(gpio.transitions(27), vec![
+            Transition { at: ms(5), level: Level::High },
+            Transition { at: ms(25), level: Level::Low },
+        ]);
+    }
+}
@@ -0,0 +1,370 @@
+// Morse code on GPIO, for headless diagnostics.
+//
+// Encoder: text -> `BlinkPattern`, so it plays on any output (LED, active
+// buzzer, ...) through `PatternPlayer`, or blocking with `play`:
+//
+// ```rust
+// let timing = MorseTiming::new(15)?;
+// status_led.play(Morse::encode("SOS", &timing));
+// Morse::play(&mut buzzer, "CQ CQ", &timing);
+// ```
+//
+// Decoder: debounced key presses -> text, e.g. from the button on GPIO17:
+//
+// ```rust
+// let button = GpioUtil::create_input_pin(17)?;
+// let message = Morse::read_message(&button, &MorseTiming::new(8)?, Duration::from_secs(3));
+// ```
+//
+// Timing follows the PARIS standard: 1 unit = `1200 / wpm` ms, dot = 1 unit,
+// dash = 3, gap inside a letter = 1, between letters = 3, between words = 7.
+// With Farnsworth spacing the letters keep their speed but the gaps between
+// letters and words are stretched to reach the lower overall speed.
+
+use std::time::Duration;
+use crate::{
+    blink::{ BlinkPattern, Repeat },
+    clock::{ Clock, SystemClock },
+    debounce::{ ButtonEdge, DebounceConfig, Debouncer },
+    digital::{ DigitalInput, DigitalOutput },
+    error::{ GpioError, Result },
+    PinState,
+};
+
+const MORSE_TABLE: &[(char, &str)] = &[
+    ('A', ".-"), ('B', "-..."), ('C', "-.-."), ('D', "-.."), ('E', "."), ('F', "..-."),
+    ('G', "--."), ('H', "...."), ('I', ".."), ('J', ".---"), ('K', "-.-"), ('L', ".-.."),
+    ('M', "--"), ('N', "-."), ('O', "---"), ('P', ".--."), ('Q', "--.-"), ('R', ".-."),
+    ('S', "..."), ('T', "-"), ('U', "..-"), ('V', "...-"), ('W', ".--"), ('X', "-..-"),
+    ('Y', "-.--"), ('Z', "--.."),
+    ('0', "-----"), ('1', ".----"), ('2', "..---"), ('3', "...--"), ('4', "....-"),
+    ('5', "....."), ('6', "-...."), ('7', "--..."), ('8', "---.."), ('9', "----."),
+    ('.', ".-.-.-"), (',', "--..--"), ('?', "..--.."), ('/', "-..-."), ('=', "-...-"),
+    ('+', ".-.-."), ('-', "-....-"), ('@', ".--.-."),
+];
+
+/// Morse speed settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MorseTiming {
+    // Speed of the letters themselves, above 0.
+    wpm: f64,
+    // Overall speed with Farnsworth spacing, above 0.
+    farnsworth_wpm: Option<f64>,
+}
+
+impl MorseTiming {
+    /// Plain timing at `wpm` words per minute, fail if `wpm` is 0.
+    pub fn new(wpm: u32) -> Result<Self> {
+        if wpm == 0 {
+            return Err(GpioError::InvalidTiming("Morse speed should be above 0 wpm".to_string()));
+        }
+        Ok(MorseTiming { wpm: wpm as f64, farnsworth_wpm: None })
+    }
+
+    /// Letters at `wpm`, gaps stretched for an overall `effective_wpm`
+    /// (no stretching unless it's lower than `wpm`). Fail if either is 0.
+    pub fn with_farnsworth(wpm: u32, effective_wpm: u32) -> Result<Self> {
+        if effective_wpm == 0 {
+            return Err(GpioError::InvalidTiming("Farnsworth speed should be above 0 wpm".to_string()));
+        }
+        Ok(MorseTiming { farnsworth_wpm: Some(effective_wpm as f64), ..MorseTiming::new(wpm)? })
+    }
+
+    /// Speed of the letters, in words per minute.
+    pub fn wpm(&self) -> f64 {
+        self.wpm
+    }
+
+    /// Overall speed with Farnsworth spacing, in words per minute.
+    pub fn farnsworth_wpm(&self) -> Option<f64> {
+        self.farnsworth_wpm
+    }
+
+    /// Length of one dot.
+    pub fn dot(&self) -> Duration {
+        Duration::from_secs_f64(1.2 / self.wpm)
+    }
+
+    /// Length of one dash.
+    pub fn dash(&self) -> Duration {
+        self.dot() * 3
+    }
+
+    /// Gap between the dots and dashes of one letter.
+    pub fn symbol_gap(&self) -> Duration {
+        self.dot()
+    }
+
+    /// Gap between letters.
+    pub fn letter_gap(&self) -> Duration {
+        match self.farnsworth_unit() {
+            Some(unit) => unit * 3,
+            None => self.dot() * 3,
+        }
+    }
+
+    /// Gap between words.
+    pub fn word_gap(&self) -> Duration {
+        match self.farnsworth_unit() {
+            Some(unit) => unit * 7,
+            None => self.dot() * 7,
+        }
+    }
+
+    // Farnsworth: "PARIS " has 31 units inside letters and 19 units of spacing.
+    // Keep the 31 at `wpm` and spread what's left of the minute over the 19.
+    fn farnsworth_unit(&self) -> Option<Duration> {
+        match self.farnsworth_wpm {
+            Some(effective_wpm) if effective_wpm < self.wpm => {
+                let spacing_secs = (60.0 * self.wpm - 37.2 * effective_wpm) / (effective_wpm * self.wpm);
+                Some(Duration::from_secs_f64(spacing_secs / 19.0))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Turns key presses into text. Feed it the debounced edges with `update`,
+/// and call it regularly without edges too, letters and words end on time.
+#[derive(Debug, Clone)]
+pub struct MorseDecoder {
+    timing: MorseTiming,
+    pressed_at: Option<Duration>,
+    released_at: Option<Duration>,
+    // Dots and dashes of the letter in progress.
+    symbols: String,
+    message: String,
+}
+
+impl MorseDecoder {
+    /// Create a decoder expecting roughly `timing`.
+    pub fn new(timing: MorseTiming) -> Self {
+        MorseDecoder { timing, pressed_at: None, released_at: None, symbols: String::new(), message: String::new() }
+    }
+
+    /// Feed the debounced edge (if any) seen at `now`.
+    pub fn update(&mut self, edge: Option<ButtonEdge>, now: Duration) {
+        match edge {
+            Some(ButtonEdge::Pressed) => {
+                self.end_gap(now);
+                self.pressed_at = Some(now);
+                self.released_at = None;
+            }
+            Some(ButtonEdge::Released) => {
+                if let Some(pressed_at) = self.pressed_at.take() {
+                    // Anything longer than 2 dots is a dash
+                    let held = now.checked_sub(pressed_at).unwrap_or_default();
+                    self.symbols.push(if held < self.timing.dot() * 2 { '.' } else { '-' });
+                }
+                self.released_at = Some(now);
+            }
+            None => {
+                if self.pressed_at.is_none() {
+                    self.end_gap(now);
+                }
+            }
+        }
+    }
+
+    // Close the letter / word if the key has been up long enough.
+    fn end_gap(&mut self, now: Duration) {
+        let released_at = match self.released_at {
+            Some(released_at) => released_at,
+            None => return,
+        };
+
+        // Halfway between the gaps tells them apart
+        let gap = now.checked_sub(released_at).unwrap_or_default();
+        let letter_threshold = (self.timing.symbol_gap() + self.timing.letter_gap()) / 2;
+        let word_threshold = (self.timing.letter_gap() + self.timing.word_gap()) / 2;
+
+        if gap >= letter_threshold && !self.symbols.is_empty() {
+            self.message.push(Morse::char_of(&self.symbols).unwrap_or('?'));
+            self.symbols.clear();
+        }
+        if gap >= word_threshold && !self.message.is_empty() && !self.message.ends_with(' ') {
+            self.message.push(' ');
+            self.released_at = None;
+        }
+    }
+
+    /// Text decoded so far, without the letter in progress.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Return the text decoded so far (including the letter in progress) and start over.
+    pub fn take_message(&mut self) -> String {
+        if !self.symbols.is_empty() {
+            self.message.push(Morse::char_of(&self.symbols).unwrap_or('?'));
+            self.symbols.clear();
+        }
+        self.released_at = None;
+
+        let message = self.message.trim_end().to_string();
+        self.message.clear();
+        message
+    }
+}
+
+/// Morse encoding, playing and reading, see the module comment.
+pub struct Morse {}
+
+impl Morse {
+
+    /// Dots and dashes of `character`, `None` if it has no Morse code.
+    pub fn code_of(character: char) -> Option<&'static str> {
+        let character = character.to_ascii_uppercase();
+        MORSE_TABLE.iter().find(|(c, _)| *c == character).map(|(_, code)| *code)
+    }
+
+    /// Character of a dots and dashes `code`.
+    pub fn char_of(code: &str) -> Option<char> {
+        MORSE_TABLE.iter().find(|(_, c)| *c == code).map(|(character, _)| *character)
+    }
+
+    /// Encode `text` into a pattern which plays once. Characters without a
+    /// Morse code are skipped.
+    pub fn encode(text: &str, timing: &MorseTiming) -> BlinkPattern {
+        let words: Vec<Vec<&str>> = text
+            .split_whitespace()
+            .map(|word| word.chars().filter_map(Morse::code_of).collect::<Vec<_>>())
+            .filter(|codes| !codes.is_empty())
+            .collect();
+
+        let mut steps = Vec::new();
+        for (word_index, word) in words.iter().enumerate() {
+            for (letter_index, code) in word.iter().enumerate() {
+                for (symbol_index, symbol) in code.chars().enumerate() {
+                    let on = if symbol == '.' { timing.dot() } else { timing.dash() };
+                    steps.push((PinState::High, on));
+
+                    let last_symbol = symbol_index == code.len() - 1;
+                    let last_letter = letter_index == word.len() - 1;
+                    let last_word = word_index == words.len() - 1;
+                    let gap = match (last_symbol, last_letter, last_word) {
+                        (false, _, _) => timing.symbol_gap(),
+                        (true, false, _) => timing.letter_gap(),
+                        (true, true, false) => timing.word_gap(),
+                        (true, true, true) => Duration::from_millis(0),
+                    };
+                    steps.push((PinState::Low, gap));
+                }
+            }
+        }
+
+        BlinkPattern::new(steps, Repeat::Times(1))
+    }
+
+    /// Play `text` on `pin`, blocking until done.
+    pub fn play<P: DigitalOutput + ?Sized>(pin: &mut P, text: &str, timing: &MorseTiming) {
+        Morse::play_with_clock(pin, text, timing, &SystemClock::new())
+    }
+
+    /// Same with `play`, but the timing goes through the given `Clock`.
+    pub fn play_with_clock<P, C>(pin: &mut P, text: &str, timing: &MorseTiming, clock: &C)
+    where
+        P: DigitalOutput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        for (state, duration) in Morse::encode(text, timing).steps.iter() {
+            match state {
+                PinState::Low => pin.set_low(),
+                PinState::High => pin.set_high(),
+            }
+            clock.sleep(*duration);
+        }
+    }
+
+    /// Read a message keyed on a button (pressed is its `active_level()`),
+    /// return once nothing was keyed for `idle_timeout` after the first press.
+    pub fn read_message<P: DigitalInput + ?Sized>(pin: &P, timing: &MorseTiming, idle_timeout: Duration) -> String {
+        Morse::read_message_with_clock(pin, timing, idle_timeout, &SystemClock::new())
+    }
+
+    /// Same with `read_message`, but sampling time goes through the given `Clock`.
+    pub fn read_message_with_clock<P, C>(pin: &P, timing: &MorseTiming, idle_timeout: Duration, clock: &C) -> String
+    where
+        P: DigitalInput + ?Sized,
+        C: Clock + ?Sized,
+    {
+        // Dots are short, keep the debouncing well below one dot
+        let config = DebounceConfig {
+            stable_time: (timing.dot() / 5).min(Duration::from_millis(20)),
+            sample_period: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let mut debouncer = Debouncer::new(config);
+        let mut decoder = MorseDecoder::new(*timing);
+        let mut last_activity: Option<Duration> = None;
+
+        loop {
+            let now = clock.now();
+            let edge = debouncer.update(debouncer.reads_pressed(pin), now);
+            if edge.is_some() || debouncer.is_pressed() {
+                last_activity = Some(now);
+            }
+            decoder.update(edge, now);
+
+            if let Some(last_activity) = last_activity {
+                if now.checked_sub(last_activity).unwrap_or_default() >= idle_timeout {
+                    return decoder.take_message();
+                }
+            }
+
+            clock.sleep(config.sample_period);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{ InputScript, MockGpio };
+    use rppal::gpio::Level;
+
+    #[test]
+    fn encode_should_follow_the_standard_timing() {
+        let timing = MorseTiming::new(20).unwrap();
+        let pattern = Morse::encode("ET", &timing);
+
+        assert_eq!(timing.dot(), Duration::from_millis(60));
+        assert_eq!(pattern.steps, vec![
+            (PinState::High, Duration::from_millis(60)),
+            (PinState::Low, Duration::from_millis(180)),
+            (PinState::High, Duration::from_millis(180)),
+            (PinState::Low, Duration::from_millis(0)),
+        ]);
+    }
+
+    #[test]
+    fn farnsworth_should_only_stretch_the_gaps() {
+        let timing = MorseTiming::with_farnsworth(20, 10).unwrap();
+
+        assert_eq!(timing.dot(), Duration::from_millis(60));
+        assert_eq!(timing.letter_gap().as_millis(), 653);
+        assert_eq!(timing.word_gap().as_millis(), 1525);
+
+        assert_eq!(MorseTiming::new(0).is_err(), true);
+        assert_eq!(MorseTiming::with_farnsworth(20, 0).is_err(), true);
+    }
+
+    #[test]
+    fn decoder_should_read_back_what_was_encoded() {
+        let timing = MorseTiming::new(10).unwrap();
+
+        // Key down (`LOW`) for every `HIGH` step of the encoded pattern
+        let mut script = InputScript::new().high_for(Duration::from_millis(200));
+        for (state, duration) in Morse::encode("SOS HI", &timing).steps.iter() {
+            let level = if *state == PinState::High { Level::Low } else { Level::High };
+            script = script.level_for(level, *duration);
+        }
+
+        let gpio = MockGpio::new();
+        let button = gpio.input_pin(17, script.high());
+        let message = Morse::read_message_with_clock(&button, &timing, Duration::from_secs(3), &gpio.clock());
+
+        assert_eq!(message, "SOS HI");
+    }
+}
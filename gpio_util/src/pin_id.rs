@@ -0,0 +1,199 @@
+// Pin numbering.
+//
+// `rppal` (and `GpioUtil`) take BCM numbers, wiring tables use the physical
+// header pin and old tutorials use wiringPi numbers. `PinId` is a BCM pin
+// built from any of them, which fails at startup if the number isn't a GPIO
+// on the 40 pin header:
+//
+// ```rust
+// let button = PinId::physical(11)?;   // GPIO17
+// let laser = PinId::wiring_pi(2)?;    // GPIO27
+// let bad = PinId::physical(6);        // Err: physical pin 6 is GND, not a GPIO
+//
+// let button_signal = GpioUtil::create_input_pin(button.bcm_number())?;
+// assert_eq!(PinId::bcm(18)?.pwm_channel(), Some(Channel::Pwm0));
+// ```
+
+use rppal::pwm::Channel;
+use std::fmt;
+use crate::error::{ GpioError, Result };
+
+/// What a header pin is, `Gpio(bcm)` or the name of the power/ground pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeaderPin {
+    Gpio(u8),
+    Power(&'static str),
+}
+
+use HeaderPin::{ Gpio, Power };
+
+// `dtoverlay=pwm-2chan` routes each hardware PWM channel to one pin.
+const PWM_2CHAN_PINS: [(Channel, u8); 2] = [(Channel::Pwm0, 18), (Channel::Pwm1, 19)];
+
+// Physical pin `n` is `HEADER[n - 1]`.
+const HEADER: [HeaderPin; 40] = [
+    Power("3.3V"), Power("5V"),
+    Gpio(2),       Power("5V"),
+    Gpio(3),       Power("GND"),
+    Gpio(4),       Gpio(14),
+    Power("GND"),  Gpio(15),
+    Gpio(17),      Gpio(18),
+    Gpio(27),      Power("GND"),
+    Gpio(22),      Gpio(23),
+    Power("3.3V"), Gpio(24),
+    Gpio(10),      Power("GND"),
+    Gpio(9),       Gpio(25),
+    Gpio(11),      Gpio(8),
+    Power("GND"),  Gpio(7),
+    Gpio(0),       Gpio(1),
+    Gpio(5),       Power("GND"),
+    Gpio(6),       Gpio(12),
+    Gpio(13),      Power("GND"),
+    Gpio(19),      Gpio(16),
+    Gpio(26),      Gpio(20),
+    Power("GND"),  Gpio(21),
+];
+
+// wiringPi number `n` is BCM `WIRING_PI[n]`, `None` for the numbers of the
+// old P5 header which the 40 pin header doesn't have.
+const WIRING_PI: [Option<u8>; 32] = [
+    Some(17), Some(18), Some(27), Some(22), Some(23), Some(24), Some(25), Some(4),
+    Some(2), Some(3), Some(8), Some(7), Some(10), Some(9), Some(11), Some(14),
+    Some(15), None, None, None, None, Some(5), Some(6), Some(13),
+    Some(19), Some(26), Some(12), Some(16), Some(20), Some(21), Some(0), Some(1),
+];
+
+/// Alternate functions of the header GPIOs which the demos care about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AltFunction {
+    Pwm0,
+    Pwm1,
+    I2cSda,
+    I2cScl,
+    SpiCe0,
+    SpiCe1,
+    SpiMiso,
+    SpiMosi,
+    SpiSclk,
+    UartTx,
+    UartRx,
+}
+
+/// A BCM GPIO which exists on the 40 pin header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PinId(u8);
+
+impl PinId {
+    /// From a BCM number, e.g. `17` for `GPIO17`.
+    pub fn bcm(bcm: u8) -> Result<PinId> {
+        if HEADER.contains(&Gpio(bcm)) { Ok(PinId(bcm)) } else { Err(GpioError::PinNotAvailable(bcm)) }
+    }
+
+    /// From a physical header pin (1-40), fails for the power and ground pins.
+    pub fn physical(physical: u8) -> Result<PinId> {
+        match HEADER.get((physical as usize).wrapping_sub(1)) {
+            Some(Gpio(bcm)) => Ok(PinId(*bcm)),
+            Some(Power(name)) => Err(GpioError::NotAGpio { physical, name }),
+            None => Err(GpioError::InvalidPhysicalPin(physical)),
+        }
+    }
+
+    /// From a wiringPi number (`gpio readall`).
+    pub fn wiring_pi(wiring_pi: u8) -> Result<PinId> {
+        match WIRING_PI.get(wiring_pi as usize) {
+            Some(Some(bcm)) => Ok(PinId(*bcm)),
+            _ => Err(GpioError::InvalidWiringPiPin(wiring_pi)),
+        }
+    }
+
+    /// Pin of a hardware PWM channel, `GPIO18` for `Pwm0` and `GPIO19` for
+    /// `Pwm1` (`dtoverlay=pwm-2chan`).
+    pub fn of_pwm_channel(channel: Channel) -> PinId {
+        let (_, bcm) = PWM_2CHAN_PINS.iter().find(|(pwm_channel, _)| *pwm_channel == channel).unwrap();
+        PinId(*bcm)
+    }
+
+    /// BCM number, what `GpioUtil` and `rppal` take.
+    pub fn bcm_number(&self) -> u8 {
+        self.0
+    }
+
+    /// Physical header pin.
+    pub fn physical_pin(&self) -> u8 {
+        HEADER.iter().position(|pin| *pin == Gpio(self.0)).unwrap() as u8 + 1
+    }
+
+    /// wiringPi number.
+    pub fn wiring_pi_number(&self) -> u8 {
+        WIRING_PI.iter().position(|bcm| *bcm == Some(self.0)).unwrap() as u8
+    }
+
+    /// Alternate functions this pin can be switched to.
+    pub fn alt_functions(&self) -> &'static [AltFunction] {
+        match self.0 {
+            2 => &[AltFunction::I2cSda],
+            3 => &[AltFunction::I2cScl],
+            7 => &[AltFunction::SpiCe1],
+            8 => &[AltFunction::SpiCe0],
+            9 => &[AltFunction::SpiMiso],
+            10 => &[AltFunction::SpiMosi],
+            11 => &[AltFunction::SpiSclk],
+            12 | 18 => &[AltFunction::Pwm0],
+            13 | 19 => &[AltFunction::Pwm1],
+            14 => &[AltFunction::UartTx],
+            15 => &[AltFunction::UartRx],
+            _ => &[],
+        }
+    }
+
+    /// Hardware PWM channel `dtoverlay=pwm-2chan` routes to this pin, if any.
+    pub fn pwm_channel(&self) -> Option<Channel> {
+        PWM_2CHAN_PINS.iter().find(|(_, bcm)| *bcm == self.0).map(|(channel, _)| *channel)
+    }
+}
+
+impl fmt::Display for PinId {
+    /// `GPIO17 (pin 11)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GPIO{} (pin {})", self.0, self.physical_pin())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn every_numbering_should_give_the_same_pin() {
+        let button = PinId::physical(11).unwrap();
+
+        assert_eq!(button, PinId::bcm(17).unwrap());
+        assert_eq!(button, PinId::wiring_pi(0).unwrap());
+        assert_eq!(button.wiring_pi_number(), 0);
+        assert_eq!(button.to_string(), "GPIO17 (pin 11)");
+    }
+
+    #[test]
+    fn power_pins_should_be_rejected() {
+        match PinId::physical(6) {
+            Err(GpioError::NotAGpio { physical, name }) => {
+                assert_eq!(physical, 6);
+                assert_eq!(name, "GND");
+            }
+            other => panic!("expected `NotAGpio`, got {:?}", other),
+        }
+        assert_eq!(PinId::physical(41).is_err(), true);
+        assert_eq!(PinId::bcm(28).is_err(), true);
+        assert_eq!(PinId::wiring_pi(17).is_err(), true);
+    }
+
+    #[test]
+    fn alt_functions_should_be_known() {
+        assert_eq!(PinId::physical(12).unwrap().pwm_channel(), Some(Channel::Pwm0));
+        assert_eq!(PinId::of_pwm_channel(Channel::Pwm1).pwm_channel(), Some(Channel::Pwm1));
+        assert_eq!(PinId::bcm(17).unwrap().pwm_channel(), None);
+        assert_eq!(PinId::physical(3).unwrap().alt_functions(), &[AltFunction::I2cSda]);
+        assert_eq!(PinId::bcm(17).unwrap().alt_functions().is_empty(), true);
+    }
+}
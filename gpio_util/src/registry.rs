@@ -0,0 +1,306 @@
+// Which BCM pin is used by whom, and for what.
+//
+// `rppal` only stops the same `Pin` being taken twice, it doesn't know that
+// GPIO18 is PWM0 or that GPIO2/3 belong to I2C. Claim pins through a
+// `PinRegistry` (usually the process wide `PinRegistry::global()`) and a
+// conflicting claim fails with `GpioError::PinClaimed` instead:
+//
+// ```rust
+// let registry = PinRegistry::global();
+// let led = registry.output_pin(18, "status LED")?;
+// let _servo = registry.claim_pwm(Channel::Pwm0, "gate servo")?; // Err: pin 18 is claimed as output
+//
+// for allocation in registry.allocations() {
+//     println!("{}", allocation);
+// }
+// ```
+//
+// A claim is released when its `PinClaim` (or the `ClaimedPin` holding it) is dropped.
+
+use rppal::{
+    gpio::{ InputPin, Level, OutputPin },
+    pwm::Channel,
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::{ Deref, DerefMut },
+    sync::{ mpsc::Receiver, Arc, Mutex, OnceLock },
+    time::Duration,
+};
+use crate::{
+    digital::{ ActiveLevel, DigitalInput, DigitalOutput },
+    edge::{ Edge, EdgeInput },
+    error::{ GpioError, Result },
+    input::{ ConfiguredInput, InputConfig },
+    pin_id::PinId,
+    GpioUtil,
+};
+
+const I2C1_PINS: [u8; 2] = [2, 3];
+// CE1, CE0, MISO, MOSI, SCLK
+const SPI0_PINS: [u8; 5] = [7, 8, 9, 10, 11];
+
+/// What a pin is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinMode {
+    Input,
+    Output,
+    Pwm,
+    I2c,
+    Spi,
+}
+
+impl fmt::Display for PinMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PinMode::Input => "input",
+            PinMode::Output => "output",
+            PinMode::Pwm => "PWM",
+            PinMode::I2c => "I2C",
+            PinMode::Spi => "SPI",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One claimed pin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinAllocation {
+    pub pin: u8,
+    pub mode: PinMode,
+    pub owner: String,
+}
+
+impl fmt::Display for PinAllocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BCM {:>2}  {:<6}  {}", self.pin, self.mode.to_string(), self.owner)
+    }
+}
+
+type Claims = Arc<Mutex<BTreeMap<u8, PinAllocation>>>;
+
+/// Keeps track of the claimed BCM pins.
+#[derive(Debug, Clone, Default)]
+pub struct PinRegistry {
+    claims: Claims,
+}
+
+impl PinRegistry {
+    /// Create an empty registry, mostly for tests, use `global` otherwise.
+    pub fn new() -> Self {
+        PinRegistry::default()
+    }
+
+    /// The registry shared by the whole process.
+    pub fn global() -> &'static PinRegistry {
+        static GLOBAL: OnceLock<PinRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(PinRegistry::new)
+    }
+
+    /// Claim `pin` for `mode`, fail if anyone (including `owner`) holds it already.
+    pub fn claim(&self, pin: u8, mode: PinMode, owner: &str) -> Result<PinClaim> {
+        self.claim_pins(&[pin], mode, owner)
+    }
+
+    /// Claim all of `pins` or none of them.
+    pub fn claim_pins(&self, pins: &[u8], mode: PinMode, owner: &str) -> Result<PinClaim> {
+        let mut claims = self.claims.lock().unwrap();
+
+        if let Some(taken) = pins.iter().find_map(|pin| claims.get(pin)) {
+            return Err(GpioError::PinClaimed { pin: taken.pin, mode: taken.mode, owner: taken.owner.clone() });
+        }
+
+        for pin in pins.iter() {
+            claims.insert(*pin, PinAllocation { pin: *pin, mode, owner: owner.to_string() });
+        }
+
+        Ok(PinClaim { pins: pins.to_vec(), claims: self.claims.clone() })
+    }
+
+    /// Claim the pin behind a hardware PWM channel, `GPIO18` for `Pwm0` and
+    /// `GPIO19` for `Pwm1` (`dtoverlay=pwm-2chan`).
+    pub fn claim_pwm(&self, channel: Channel, owner: &str) -> Result<PinClaim> {
+        self.claim(PinId::of_pwm_channel(channel).bcm_number(), PinMode::Pwm, owner)
+    }
+
+    /// Claim `SDA`/`SCL` of `/dev/i2c-1`, `GPIO2` and `GPIO3`.
+    pub fn claim_i2c(&self, owner: &str) -> Result<PinClaim> {
+        self.claim_pins(&I2C1_PINS, PinMode::I2c, owner)
+    }
+
+    /// Claim the `SPI0` pins, `GPIO7` to `GPIO11`.
+    pub fn claim_spi(&self, owner: &str) -> Result<PinClaim> {
+        self.claim_pins(&SPI0_PINS, PinMode::Spi, owner)
+    }
+
+    /// Claim `pin` and create the `InputPin`.
+    pub fn input_pin(&self, pin: u8, owner: &str) -> Result<ClaimedPin<InputPin>> {
+        let claim = self.claim(pin, PinMode::Input, owner)?;
+        Ok(ClaimedPin { pin: GpioUtil::create_input_pin(pin)?, claim })
+    }
+
+    /// Claim `pin` and create the input with the pull resistor and active level of `config`.
+    pub fn configured_input_pin(&self, pin: u8, config: InputConfig, owner: &str) -> Result<ClaimedPin<ConfiguredInput<InputPin>>> {
+        let claim = self.claim(pin, PinMode::Input, owner)?;
+        Ok(ClaimedPin { pin: GpioUtil::create_configured_input_pin(pin, config)?, claim })
+    }
+
+    /// Claim `pin` and create the `OutputPin`.
+    pub fn output_pin(&self, pin: u8, owner: &str) -> Result<ClaimedPin<OutputPin>> {
+        let claim = self.claim(pin, PinMode::Output, owner)?;
+        Ok(ClaimedPin { pin: GpioUtil::create_output_pin(pin)?, claim })
+    }
+
+    /// Current claims, ordered by pin.
+    pub fn allocations(&self) -> Vec<PinAllocation> {
+        self.claims.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Holds the claim on one or more pins, they're released on drop.
+#[derive(Debug)]
+pub struct PinClaim {
+    pins: Vec<u8>,
+    claims: Claims,
+}
+
+impl PinClaim {
+    /// The claimed pins.
+    pub fn pins(&self) -> &[u8] {
+        &self.pins
+    }
+}
+
+impl Drop for PinClaim {
+    fn drop(&mut self) {
+        let mut claims = self.claims.lock().unwrap();
+        for pin in self.pins.iter() {
+            claims.remove(pin);
+        }
+    }
+}
+
+/// A pin together with its claim, derefs to the pin.
+#[derive(Debug)]
+pub struct ClaimedPin<P> {
+    pin: P,
+    claim: PinClaim,
+}
+
+impl<P> ClaimedPin<P> {
+    /// Attach `claim` to an already created `pin`, e.g. a `MemoryPin`.
+    pub fn new(pin: P, claim: PinClaim) -> Self {
+        ClaimedPin { pin, claim }
+    }
+
+    /// The claim held by this pin.
+    pub fn claim(&self) -> &PinClaim {
+        &self.claim
+    }
+}
+
+impl<P> Deref for ClaimedPin<P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        &self.pin
+    }
+}
+
+impl<P> DerefMut for ClaimedPin<P> {
+    fn deref_mut(&mut self) -> &mut P {
+        &mut self.pin
+    }
+}
+
+impl<P: DigitalInput> DigitalInput for ClaimedPin<P> {
+    fn read(&self) -> Level {
+        self.pin.read()
+    }
+
+    fn active_level(&self) -> ActiveLevel {
+        self.pin.active_level()
+    }
+}
+
+impl<P: DigitalOutput> DigitalOutput for ClaimedPin<P> {
+    fn set_high(&mut self) {
+        self.pin.set_high()
+    }
+
+    fn set_low(&mut self) {
+        self.pin.set_low()
+    }
+
+    fn is_set_high(&self) -> bool {
+        self.pin.is_set_high()
+    }
+
+    fn toggle(&mut self) {
+        self.pin.toggle()
+    }
+}
+
+impl<P: EdgeInput> EdgeInput for ClaimedPin<P> {
+    fn subscribe<F>(&mut self, edge: Edge, callback: F) -> Result<()>
+    where
+        F: FnMut(Level) + Send + 'static,
+    {
+        self.pin.subscribe(edge, callback)
+    }
+
+    fn unsubscribe(&mut self) -> Result<()> {
+        self.pin.unsubscribe()
+    }
+
+    fn subscribe_channel(&mut self, edge: Edge) -> Result<Receiver<Level>> {
+        self.pin.subscribe_channel(edge)
+    }
+
+    fn wait_for_edge(&mut self, edge: Edge, timeout: Option<Duration>) -> Result<Option<Level>> {
+        self.pin.wait_for_edge(edge, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::MemoryPin;
+
+    #[test]
+    fn conflicting_claims_should_be_rejected() {
+        let registry = PinRegistry::new();
+        let _led = registry.claim(18, PinMode::Output, "status LED").unwrap();
+
+        match registry.claim_pwm(Channel::Pwm0, "gate servo") {
+            Err(GpioError::PinClaimed { pin, mode, owner }) => {
+                assert_eq!(pin, 18);
+                assert_eq!(mode, PinMode::Output);
+                assert_eq!(owner, "status LED");
+            }
+            other => panic!("expected `PinClaimed`, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dropped_claims_should_release_the_pins() {
+        let registry = PinRegistry::new();
+        let i2c = registry.claim_i2c("PCF8591").unwrap();
+
+        assert_eq!(registry.claim(3, PinMode::Input, "button").is_err(), true);
+        drop(i2c);
+        assert_eq!(registry.claim(3, PinMode::Input, "button").is_ok(), true);
+    }
+
+    #[test]
+    fn allocations_should_list_every_claimed_pin() {
+        let registry = PinRegistry::new();
+        let _button = ClaimedPin::new(MemoryPin::default(), registry.claim(17, PinMode::Input, "button").unwrap());
+        let _i2c = registry.claim_i2c("PCF8591").unwrap();
+
+        let pins: Vec<(u8, PinMode)> = registry.allocations().iter().map(|allocation| (allocation.pin, allocation.mode)).collect();
+        assert_eq!(pins, vec![(2, PinMode::I2c), (3, PinMode::I2c), (17, PinMode::Input)]);
+    }
+}
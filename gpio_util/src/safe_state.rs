@@ -0,0 +1,251 @@
+// Safe output levels on exit, panic and `Ctrl+C`.
+//
+// `rppal` pins reset on drop, but a panic in another thread or a signal can
+// still leave the laser or the buzzer on. Every output registered in a
+// `SafeState` declares its safe level, and is driven to it:
+//
+// - right away when it's registered,
+// - when its `SafeOutput` handle is dropped,
+// - from the panic hook (`install_panic_hook`),
+// - from the `SIGINT`/`SIGTERM` handler (`install_signal_handler`).
+//
+// ```rust
+// let safe_state = SafeState::global();
+// safe_state.install_panic_hook();
+// safe_state.install_signal_handler(move || shutdown.store(true, Ordering::SeqCst))?;
+//
+// let mut laser = safe_state.register(GpioUtil::create_output_pin(27)?, PinState::Low, "laser");
+// // Active `LOW` buzzer, silent when `HIGH`
+// let mut buzzer = safe_state.register(GpioUtil::create_output_pin(19)?, PinState::High, "buzzer");
+// ```
+
+use std::{
+    panic,
+    sync::{ Arc, Mutex, MutexGuard, OnceLock, TryLockError, Weak },
+    thread,
+    time::Duration,
+};
+use crate::{
+    digital::DigitalOutput,
+    error::{ GpioError, Result },
+    PinState,
+};
+
+// How long the panic hook waits for a pin which is locked by another thread.
+const LOCK_RETRIES: u32 = 50;
+const LOCK_RETRY_PERIOD: Duration = Duration::from_millis(2);
+
+type SharedOutput = Arc<Mutex<dyn DigitalOutput + Send>>;
+
+struct Entry {
+    name: String,
+    safe_state: PinState,
+    pin: Weak<Mutex<dyn DigitalOutput + Send>>,
+}
+
+/// Knows every registered output and its safe level.
+#[derive(Default)]
+pub struct SafeState {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl SafeState {
+    /// Create an empty manager, mostly for tests, use `global` otherwise.
+    pub fn new() -> Self {
+        SafeState::default()
+    }
+
+    /// The manager shared by the whole process, the one the hooks use.
+    pub fn global() -> &'static SafeState {
+        static GLOBAL: OnceLock<SafeState> = OnceLock::new();
+        GLOBAL.get_or_init(SafeState::new)
+    }
+
+    /// Register `pin` with its safe level, it's driven there right away.
+    pub fn register<P: DigitalOutput + Send + 'static>(&self, pin: P, safe_state: PinState, name: &str) -> SafeOutput<P> {
+        let pin = Arc::new(Mutex::new(pin));
+        drive(&mut *lock(&pin), safe_state);
+
+        let shared: SharedOutput = pin.clone();
+        let mut entries = lock(&self.entries);
+        // Forget the dropped ones while we're here
+        entries.retain(|entry| entry.pin.strong_count() > 0);
+        entries.push(Entry { name: name.to_string(), safe_state, pin: Arc::downgrade(&shared) });
+
+        SafeOutput { pin, safe_state }
+    }
+
+    /// Drive every registered output to its safe level, return their names.
+    pub fn drive_all_safe(&self) -> Vec<String> {
+        let mut driven = Vec::new();
+        for entry in lock(&self.entries).iter() {
+            let pin = match entry.pin.upgrade() {
+                Some(pin) => pin,
+                None => continue,
+            };
+
+            let locked = try_lock_for_a_while(&pin);
+            if let Some(mut guard) = locked {
+                drive(&mut *guard, entry.safe_state);
+                driven.push(entry.name.clone());
+            }
+        }
+        driven
+    }
+
+    /// Drive all outputs safe when any thread panics, then run the previous hook.
+    pub fn install_panic_hook(&'static self) {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            self.drive_all_safe();
+            previous_hook(info);
+        }));
+    }
+
+    /// On `SIGINT`/`SIGTERM` (and `SIGHUP`) drive all outputs safe, then call
+    /// `then`, e.g. to ask the main loop to stop, or `process::exit`.
+    /// Only one signal handler can be installed per process.
+    pub fn install_signal_handler<F>(&'static self, mut then: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        ctrlc::set_handler(move || {
+            self.drive_all_safe();
+            then();
+        })
+        .map_err(|error| GpioError::SignalHandler(error.to_string()))
+    }
+}
+
+/// A registered output, use it like the pin itself. Dropping it drives the
+/// pin to its safe level.
+pub struct SafeOutput<P: DigitalOutput> {
+    pin: Arc<Mutex<P>>,
+    safe_state: PinState,
+}
+
+impl<P: DigitalOutput> SafeOutput<P> {
+    /// The declared safe level.
+    pub fn safe_state(&self) -> PinState {
+        self.safe_state
+    }
+
+    /// Drive this pin to its safe level now.
+    pub fn drive_safe(&mut self) {
+        drive(&mut *lock(&self.pin), self.safe_state);
+    }
+}
+
+impl<P: DigitalOutput> DigitalOutput for SafeOutput<P> {
+    fn set_high(&mut self) {
+        lock(&self.pin).set_high()
+    }
+
+    fn set_low(&mut self) {
+        lock(&self.pin).set_low()
+    }
+
+    fn is_set_high(&self) -> bool {
+        lock(&self.pin).is_set_high()
+    }
+
+    fn toggle(&mut self) {
+        lock(&self.pin).toggle()
+    }
+}
+
+impl<P: DigitalOutput> Drop for SafeOutput<P> {
+    fn drop(&mut self) {
+        self.drive_safe();
+    }
+}
+
+fn drive<P: DigitalOutput + ?Sized>(pin: &mut P, state: PinState) {
+    match state {
+        PinState::Low => pin.set_low(),
+        PinState::High => pin.set_high(),
+    }
+}
+
+// A panic while holding the lock doesn't matter here, the pin is still fine.
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// The panicking thread might hold the pin, don't wait for it forever.
+fn try_lock_for_a_while<T: ?Sized>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    for _ in 0..LOCK_RETRIES {
+        match mutex.try_lock() {
+            Ok(guard) => return Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => return Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => thread::sleep(LOCK_RETRY_PERIOD),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::MemoryPin;
+    use rppal::gpio::Level;
+
+    #[test]
+    fn register_and_drop_should_drive_the_safe_level() {
+        let buzzer = MemoryPin::new(Level::Low);
+        let safe_state = SafeState::new();
+
+        let mut safe_buzzer = safe_state.register(buzzer.clone(), PinState::High, "buzzer");
+        assert_eq!(buzzer.level(), Level::High);
+
+        safe_buzzer.set_low();
+        assert_eq!(buzzer.level(), Level::Low);
+
+        drop(safe_buzzer);
+        assert_eq!(buzzer.level(), Level::High);
+        assert_eq!(safe_state.drive_all_safe().is_empty(), true);
+    }
+
+    #[test]
+    fn drive_all_safe_should_reset_every_output() {
+        let laser = MemoryPin::new(Level::Low);
+        let buzzer = MemoryPin::new(Level::Low);
+        let safe_state = SafeState::new();
+        let mut safe_laser = safe_state.register(laser.clone(), PinState::Low, "laser");
+        let mut safe_buzzer = safe_state.register(buzzer.clone(), PinState::High, "buzzer");
+
+        safe_laser.set_high();
+        safe_buzzer.set_low();
+
+        assert_eq!(safe_state.drive_all_safe(), vec!["laser".to_string(), "buzzer".to_string()]);
+        assert_eq!(laser.level(), Level::Low);
+        assert_eq!(buzzer.level(), Level::High);
+    }
+
+    #[test]
+    fn panic_hook_should_drive_the_outputs_safe() {
+        let laser = MemoryPin::new(Level::Low);
+        let safe_state: &'static SafeState = Box::leak(Box::new(SafeState::new()));
+        // Put the test harness hook back afterwards, the other tests shouldn't run ours
+        let harness_hook = panic::take_hook();
+        safe_state.install_panic_hook();
+
+        // Owned out here too, so unwinding the worker doesn't drop (and reset) it,
+        // only the hook can bring the laser back `LOW`
+        let safe_laser = Arc::new(Mutex::new(safe_state.register(laser.clone(), PinState::Low, "laser")));
+        let worker_laser = safe_laser.clone();
+        let worker = thread::spawn(move || {
+            worker_laser.lock().unwrap().set_high();
+            panic!("laser thread panicked on purpose");
+        });
+
+        let panicked = worker.join().is_err();
+        let _ = panic::take_hook();
+        panic::set_hook(harness_hook);
+
+        assert_eq!(panicked, true);
+        assert_eq!(laser.level(), Level::Low);
+        assert_eq!(Arc::strong_count(&safe_laser), 1);
+    }
+}
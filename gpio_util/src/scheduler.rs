@@ -0,0 +1,277 @@
+// Timed output changes applied by a background timer thread.
+//
+// `GpioUtil::set_pin_state` with a delay blocks the caller, so driving a few
+// LEDs with different timing needs a thread each. `OutputScheduler` owns the
+// pins and one timer thread applies the queued changes:
+//
+// ```rust
+// let scheduler = OutputScheduler::new();
+// let led = scheduler.add_pin(GpioUtil::create_output_pin(18)?);
+// let buzzer = scheduler.add_pin(GpioUtil::create_output_pin(19)?);
+//
+// // "High now, Low after 1s", doesn't block
+// let blink = scheduler.schedule_sequence(led, &[
+//     (PinState::High, Duration::from_secs(0)),
+//     (PinState::Low, Duration::from_secs(1)),
+// ]);
+// scheduler.schedule(buzzer, PinState::High, Duration::from_millis(300));
+//
+// // Changed our mind, drop what's left of the blink
+// blink.cancel();
+// ```
+
+use std::{
+    cmp::Ordering,
+    collections::{ BinaryHeap, HashMap },
+    sync::{ Arc, Condvar, Mutex },
+    thread,
+    time::{ Duration, Instant },
+};
+use crate::{ digital::DigitalOutput, PinState };
+
+/// A pin owned by an `OutputScheduler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScheduledPin(usize);
+
+// One queued change, ordered by time and then by scheduling order.
+struct Job {
+    at: Instant,
+    seq: u64,
+    group: u64,
+    pin: ScheduledPin,
+    state: PinState,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    // Reversed, so `BinaryHeap` pops the earliest job first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at.cmp(&self.at).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    pins: HashMap<ScheduledPin, Box<dyn DigitalOutput + Send>>,
+    next_pin: usize,
+    queue: BinaryHeap<Job>,
+    next_seq: u64,
+    next_group: u64,
+    // Jobs left per group, cancelled groups are removed from here.
+    pending: HashMap<u64, usize>,
+    shutting_down: bool,
+}
+
+type Shared = Arc<(Mutex<SchedulerState>, Condvar)>;
+
+/// Returned by `OutputScheduler::schedule*`, used to cancel the changes
+/// which haven't been applied yet.
+#[derive(Clone)]
+pub struct ScheduleHandle {
+    group: u64,
+    shared: Shared,
+}
+
+impl ScheduleHandle {
+    /// Drop the changes which haven't been applied yet. Return `false` if
+    /// there was nothing left to cancel.
+    pub fn cancel(&self) -> bool {
+        let (state, timer) = &*self.shared;
+        let cancelled = state.lock().unwrap().pending.remove(&self.group).is_some();
+        timer.notify_all();
+        cancelled
+    }
+
+    /// `true` while some of the changes are still waiting.
+    pub fn is_pending(&self) -> bool {
+        self.shared.0.lock().unwrap().pending.contains_key(&self.group)
+    }
+}
+
+/// Owns output pins and applies timed changes to them from one background thread.
+pub struct OutputScheduler {
+    shared: Shared,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl OutputScheduler {
+    /// Create the scheduler and start its timer thread.
+    pub fn new() -> Self {
+        let shared: Shared = Arc::new((Mutex::new(SchedulerState::default()), Condvar::new()));
+        let worker_shared = shared.clone();
+        let worker = thread::spawn(move || OutputScheduler::run(worker_shared));
+
+        OutputScheduler { shared, worker: Some(worker) }
+    }
+
+    /// Hand `pin` over to the scheduler.
+    pub fn add_pin<P: DigitalOutput + Send + 'static>(&self, pin: P) -> ScheduledPin {
+        let mut state = self.shared.0.lock().unwrap();
+        let id = ScheduledPin(state.next_pin);
+        state.next_pin += 1;
+        state.pins.insert(id, Box::new(pin));
+        id
+    }
+
+    /// Take `pin` back, its pending changes are skipped.
+    pub fn remove_pin(&self, pin: ScheduledPin) -> Option<Box<dyn DigitalOutput + Send>> {
+        self.shared.0.lock().unwrap().pins.remove(&pin)
+    }
+
+    /// Set `pin` to `state` after `delay`, without blocking.
+    pub fn schedule(&self, pin: ScheduledPin, state: PinState, delay: Duration) -> ScheduleHandle {
+        self.schedule_sequence(pin, &[(state, delay)])
+    }
+
+    /// Queue several changes on `pin`, each delay counts from now. One handle
+    /// cancels all of them.
+    pub fn schedule_sequence(&self, pin: ScheduledPin, steps: &[(PinState, Duration)]) -> ScheduleHandle {
+        let (state, timer) = &*self.shared;
+        let mut state = state.lock().unwrap();
+        let now = Instant::now();
+
+        // Even an empty sequence gets its own group, so its handle can't cancel another one
+        let group = state.next_group;
+        state.next_group += 1;
+        for (pin_state, delay) in steps.iter() {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.queue.push(Job { at: now + *delay, seq, group, pin, state: *pin_state });
+        }
+        if !steps.is_empty() {
+            state.pending.insert(group, steps.len());
+        }
+
+        timer.notify_all();
+        ScheduleHandle { group, shared: self.shared.clone() }
+    }
+
+    // Timer thread: apply every due job, then sleep until the next one (or a new schedule).
+    fn run(shared: Shared) {
+        let (state, timer) = &*shared;
+        let mut state = state.lock().unwrap();
+
+        while !state.shutting_down {
+            let now = Instant::now();
+            while state.queue.peek().map_or(false, |job| job.at <= now) {
+                let job = state.queue.pop().unwrap();
+
+                let jobs_left = match state.pending.get_mut(&job.group) {
+                    Some(jobs_left) => { *jobs_left -= 1; *jobs_left }
+                    // Cancelled
+                    None => continue,
+                };
+                if jobs_left == 0 {
+                    state.pending.remove(&job.group);
+                }
+
+                if let Some(pin) = state.pins.get_mut(&job.pin) {
+                    match job.state {
+                        PinState::Low => pin.set_low(),
+                        PinState::High => pin.set_high(),
+                    }
+                }
+            }
+
+            state = match state.queue.peek().map(|job| job.at) {
+                Some(next_at) => timer.wait_timeout(state, next_at.saturating_duration_since(now)).unwrap().0,
+                None => timer.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+impl Default for OutputScheduler {
+    fn default() -> Self {
+        OutputScheduler::new()
+    }
+}
+
+impl Drop for OutputScheduler {
+    fn drop(&mut self) {
+        self.shared.0.lock().unwrap().shutting_down = true;
+        self.shared.1.notify_all();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{ DigitalInput, MemoryPin };
+    use rppal::gpio::Level;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn scheduled_changes_should_apply_in_the_background() {
+        let led = MemoryPin::new(Level::Low);
+        let scheduler = OutputScheduler::new();
+        let scheduled_led = scheduler.add_pin(led.clone());
+
+        let handle = scheduler.schedule_sequence(scheduled_led, &[
+            (PinState::High, ms(0)),
+            (PinState::Low, ms(100)),
+        ]);
+
+        thread::sleep(ms(50));
+        assert_eq!(led.is_high(), true);
+        assert_eq!(handle.is_pending(), true);
+
+        thread::sleep(ms(100));
+        assert_eq!(led.is_low(), true);
+        assert_eq!(handle.is_pending(), false);
+    }
+
+    #[test]
+    fn cancelled_changes_should_not_apply() {
+        let led = MemoryPin::new(Level::Low);
+        let buzzer = MemoryPin::new(Level::Low);
+        let scheduler = OutputScheduler::new();
+        let scheduled_led = scheduler.add_pin(led.clone());
+        let scheduled_buzzer = scheduler.add_pin(buzzer.clone());
+
+        let led_handle = scheduler.schedule(scheduled_led, PinState::High, ms(50));
+        scheduler.schedule(scheduled_buzzer, PinState::High, ms(50));
+        assert_eq!(led_handle.cancel(), true);
+
+        thread::sleep(ms(100));
+        assert_eq!(led.is_low(), true);
+        assert_eq!(buzzer.is_high(), true);
+        assert_eq!(led_handle.cancel(), false);
+    }
+
+    #[test]
+    fn empty_sequences_should_not_cancel_later_changes() {
+        let led = MemoryPin::new(Level::Low);
+        let scheduler = OutputScheduler::new();
+        let scheduled_led = scheduler.add_pin(led.clone());
+
+        let empty_handle = scheduler.schedule_sequence(scheduled_led, &[]);
+        let handle = scheduler.schedule(scheduled_led, PinState::High, ms(50));
+        assert_eq!(empty_handle.cancel(), false);
+        assert_eq!(empty_handle.is_pending(), false);
+
+        thread::sleep(ms(100));
+        assert_eq!(led.is_high(), true);
+        assert_eq!(handle.is_pending(), false);
+    }
+}
@@ -0,0 +1,275 @@
+// Software PWM on any output pin.
+//
+// Only GPIO18/GPIO19 have hardware PWM. `SoftPwm` toggles any output pin from
+// a background thread instead, good enough for dimming LEDs and for buzzer
+// tones, not for servos (they twitch on jitter, use the hardware channels).
+//
+// ```rust
+// let led = SoftPwm::new(GpioUtil::create_output_pin(23)?, 200.0, 0.25)?;
+// led.set_duty_cycle(0.75)?;
+//
+// let hybrid = PwmTiming::Hybrid { spin_margin: Duration::from_micros(200) };
+// let buzzer = SoftPwm::with_timing(GpioUtil::create_output_pin(24)?, 440.0, 0.5, hybrid)?;
+// buzzer.set_frequency(880.0)?;
+// ```
+//
+// Edges can't be more exact than the thread wakes up, which is the trade-off
+// `PwmTiming` picks. `cargo bench --bench soft_pwm` on a shared x86 VM, 1kHz
+// 25% duty, error of the rising edge period:
+//
+// | Timing           | avg    | p99     | CPU (one core) |
+// | ---------------- | ------ | ------- | -------------- |
+// | `Sleep`          | ~62µs  | ~370µs  | ~6%            |
+// | `Hybrid` (50µs)  | ~48µs  | ~330µs  | ~7%            |
+// | `Hybrid` (200µs) | ~23µs  | ~450µs  | ~25%           |
+// | `Spin`           | ~17µs  | ~460µs  | ~98%           |
+//
+// The p99 and max are the scheduler preempting the thread, no mode avoids
+// that. A busy VM is the worst case, run the bench on the Pi itself before
+// picking a mode: LED dimming is fine with `Sleep` (the default, several pins
+// at once cost next to nothing), tones and servos want `Hybrid`.
+
+use std::{
+    sync::{
+        atomic::{ AtomicBool, Ordering },
+        Arc, Mutex,
+    },
+    thread,
+    time::{ Duration, Instant },
+};
+use crate::{
+    digital::DigitalOutput,
+    error::{ GpioError, Result },
+};
+
+/// Highest frequency accepted by `SoftPwm`, the edges are mostly timing
+/// error above this.
+pub const SOFT_PWM_MAX_FREQUENCY: f64 = 10_000.0;
+
+/// How the PWM thread waits for the next edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwmTiming {
+    /// `thread::sleep` until every edge. Next to no CPU, edges are late by
+    /// the wakeup latency.
+    Sleep,
+    /// Sleep until `spin_margin` before the edge, busy-wait the rest. The
+    /// choice for tones and servos, at about 25% of a core per pin with 200µs.
+    Hybrid { spin_margin: Duration },
+    /// Busy-wait every edge, one core at 100%.
+    Spin,
+}
+
+impl Default for PwmTiming {
+    /// `Sleep`, cheap enough to dim several LEDs at once.
+    fn default() -> Self {
+        PwmTiming::Sleep
+    }
+}
+
+impl PwmTiming {
+    fn wait_until(&self, deadline: Instant) {
+        let spin_margin = match self {
+            PwmTiming::Sleep => Duration::from_secs(0),
+            PwmTiming::Hybrid { spin_margin } => *spin_margin,
+            PwmTiming::Spin => Duration::MAX,
+        };
+
+        let now = Instant::now();
+        if deadline <= now {
+            return;
+        }
+
+        let left = deadline - now;
+        if left > spin_margin {
+            thread::sleep(left - spin_margin);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    frequency: f64,
+    duty_cycle: f64,
+}
+
+impl Settings {
+    // (high, low) part of one period.
+    fn split(&self) -> (Duration, Duration) {
+        let period = Duration::from_secs_f64(1.0 / self.frequency);
+        let high = period.mul_f64(self.duty_cycle);
+        (high, period - high)
+    }
+}
+
+fn check_frequency(frequency: f64) -> Result<()> {
+    if frequency > 0.0 && frequency <= SOFT_PWM_MAX_FREQUENCY {
+        Ok(())
+    } else {
+        Err(GpioError::InvalidPwmSetting(format!(
+            "frequency {}Hz is out of range, software PWM supports (0, {}]Hz",
+            frequency, SOFT_PWM_MAX_FREQUENCY
+        )))
+    }
+}
+
+fn check_duty_cycle(duty_cycle: f64) -> Result<()> {
+    if (0.0..=1.0).contains(&duty_cycle) {
+        Ok(())
+    } else {
+        Err(GpioError::InvalidPwmSetting(format!("duty cycle {} is out of range, it should be in [0.0, 1.0]", duty_cycle)))
+    }
+}
+
+/// PWM on any output pin, driven by a background thread. The pin is set
+/// `LOW` when it's dropped.
+pub struct SoftPwm {
+    settings: Arc<Mutex<Settings>>,
+    running: Arc<AtomicBool>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl SoftPwm {
+    /// Start PWM on `pin` with the default timing (`PwmTiming::Sleep`).
+    pub fn new<P: DigitalOutput + Send + 'static>(pin: P, frequency: f64, duty_cycle: f64) -> Result<Self> {
+        SoftPwm::with_timing(pin, frequency, duty_cycle, PwmTiming::default())
+    }
+
+    /// Start PWM on `pin`, `timing` picks the jitter vs CPU trade-off.
+    pub fn with_timing<P>(pin: P, frequency: f64, duty_cycle: f64, timing: PwmTiming) -> Result<Self>
+    where
+        P: DigitalOutput + Send + 'static,
+    {
+        check_frequency(frequency)?;
+        check_duty_cycle(duty_cycle)?;
+
+        let settings = Arc::new(Mutex::new(Settings { frequency, duty_cycle }));
+        let running = Arc::new(AtomicBool::new(true));
+        let worker = {
+            let settings = settings.clone();
+            let running = running.clone();
+            thread::spawn(move || SoftPwm::run(pin, &settings, &running, timing))
+        };
+
+        Ok(SoftPwm { settings, running, worker: Some(worker) })
+    }
+
+    // Edges are scheduled from the previous deadline, not from "now", so the
+    // error doesn't add up over time.
+    fn run<P: DigitalOutput>(mut pin: P, settings: &Mutex<Settings>, running: &AtomicBool, timing: PwmTiming) {
+        let mut period_start = Instant::now();
+
+        while running.load(Ordering::SeqCst) {
+            let (high, low) = settings.lock().unwrap().split();
+
+            if !high.is_zero() {
+                pin.set_high();
+                timing.wait_until(period_start + high);
+            }
+            if !low.is_zero() {
+                pin.set_low();
+                timing.wait_until(period_start + high + low);
+            }
+
+            period_start += high + low;
+            // Way behind (preempted, or the period just got shorter), start over
+            // instead of firing a burst of short periods.
+            if Instant::now() > period_start + high + low {
+                period_start = Instant::now();
+            }
+        }
+
+        pin.set_low();
+    }
+
+    /// Change the frequency, applied from the next period.
+    pub fn set_frequency(&self, frequency: f64) -> Result<()> {
+        check_frequency(frequency)?;
+        self.settings.lock().unwrap().frequency = frequency;
+        Ok(())
+    }
+
+    /// Change the duty cycle (`0.0` to `1.0`), applied from the next period.
+    pub fn set_duty_cycle(&self, duty_cycle: f64) -> Result<()> {
+        check_duty_cycle(duty_cycle)?;
+        self.settings.lock().unwrap().duty_cycle = duty_cycle;
+        Ok(())
+    }
+
+    /// Current frequency in Hz.
+    pub fn frequency(&self) -> f64 {
+        self.settings.lock().unwrap().frequency
+    }
+
+    /// Current duty cycle.
+    pub fn duty_cycle(&self) -> f64 {
+        self.settings.lock().unwrap().duty_cycle
+    }
+}
+
+impl Drop for SoftPwm {
+    /// Stop the thread after the current period, the pin ends `LOW`.
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{ MemoryPin, MockGpio };
+    use rppal::gpio::Level;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn out_of_range_settings_should_be_rejected() {
+        assert_eq!(SoftPwm::new(MemoryPin::default(), 0.0, 0.5).is_err(), true);
+        assert_eq!(SoftPwm::new(MemoryPin::default(), 100.0, 1.5).is_err(), true);
+
+        let pwm = SoftPwm::new(MemoryPin::default(), 100.0, 0.5).unwrap();
+        assert_eq!(pwm.set_frequency(SOFT_PWM_MAX_FREQUENCY * 2.0).is_err(), true);
+        assert_eq!(pwm.set_duty_cycle(-0.1).is_err(), true);
+        assert_eq!(pwm.frequency(), 100.0);
+        assert_eq!(pwm.duty_cycle(), 0.5);
+    }
+
+    #[test]
+    fn full_duty_should_stay_high_and_drop_should_end_low() {
+        let led = MemoryPin::new(Level::Low);
+        let pwm = SoftPwm::with_timing(led.clone(), 500.0, 1.0, PwmTiming::Sleep).unwrap();
+
+        thread::sleep(ms(20));
+        assert_eq!(led.level(), Level::High);
+
+        pwm.set_duty_cycle(0.0).unwrap();
+        thread::sleep(ms(20));
+        assert_eq!(led.level(), Level::Low);
+
+        pwm.set_duty_cycle(1.0).unwrap();
+        thread::sleep(ms(20));
+        drop(pwm);
+        assert_eq!(led.level(), Level::Low);
+    }
+
+    #[test]
+    fn pin_should_toggle_every_period() {
+        let gpio = MockGpio::new();
+        let pwm = SoftPwm::with_timing(gpio.output_pin(23, Level::Low), 100.0, 0.5, PwmTiming::Sleep).unwrap();
+
+        thread::sleep(ms(200));
+        drop(pwm);
+
+        // 20 periods in 200ms, leave room for a slow CI machine
+        let rising_edges = gpio.transitions(23).iter().filter(|transition| transition.level == Level::High).count();
+        assert_eq!(rising_edges >= 10 && rising_edges <= 21, true);
+    }
+}
@@ -0,0 +1,62 @@
+// Give-up conditions for the blocking button helpers.
+//
+// ```rust
+// let shutdown = AtomicBool::new(false);
+//
+// // Wait at most 5s, or until another thread sets `shutdown`
+// let limit = WaitLimit::timeout(Duration::from_secs(5)).or_cancelled(&shutdown);
+// if GpioUtil::block_until_button_pressed_with_limit(&button, limit, &SystemClock::new()) {
+//     println!("pressed");
+// }
+// ```
+
+use std::{
+    sync::atomic::{ AtomicBool, Ordering },
+    time::Duration,
+};
+
+/// When a blocking wait should stop waiting. The default waits forever.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WaitLimit<'a> {
+    /// Give up after this long.
+    pub timeout: Option<Duration>,
+    /// Give up as soon as this flag becomes `true`.
+    pub cancel: Option<&'a AtomicBool>,
+}
+
+impl<'a> WaitLimit<'a> {
+    /// Never give up.
+    pub fn forever() -> Self {
+        WaitLimit::default()
+    }
+
+    /// Give up after `timeout`.
+    pub fn timeout(timeout: Duration) -> Self {
+        WaitLimit { timeout: Some(timeout), cancel: None }
+    }
+
+    /// Give up when `cancel` becomes `true`.
+    pub fn cancelled_by(cancel: &'a AtomicBool) -> Self {
+        WaitLimit { timeout: None, cancel: Some(cancel) }
+    }
+
+    /// Also give up when `cancel` becomes `true`.
+    pub fn or_cancelled(mut self, cancel: &'a AtomicBool) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// `true` when a wait which started at `started` should stop at `now`.
+    pub fn is_reached(&self, started: Duration, now: Duration) -> bool {
+        if let Some(cancel) = self.cancel {
+            if cancel.load(Ordering::SeqCst) {
+                return true;
+            }
+        }
+
+        match self.timeout {
+            Some(timeout) => now.checked_sub(started).unwrap_or_default() >= timeout,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,401 @@
+// Board wiring loaded from a TOML file, only with the `wiring` feature:
+//
+// ```toml
+// gpio_util={ path="../gpio_util", version="0.1.0", features=["wiring"] }
+// ```
+//
+// Every table is a device, `kind` says what it is. Pins can be given as BCM
+// (`pin`), physical header pin (`header_pin`) or wiringPi number (`wiring_pi`):
+//
+// ```toml
+// [button]
+// kind = "input"
+// pin = 17
+// pull = "up"         # "none" (default), "up" or "down"
+// active = "low"      # "low" (default) or "high"
+//
+// [laser]
+// kind = "output"
+// header_pin = 13
+//
+// [converter]
+// kind = "i2c"
+// bus = "/dev/i2c-1"  # default
+// address = 0x48
+//
+// [gate_servo]
+// kind = "pwm"
+// channel = 0
+// ```
+//
+// Loading checks that every pin exists and that no two devices share a pin
+// (PWM0 is GPIO18, I2C is GPIO2/3). Handles are handed out by name:
+//
+// ```rust
+// let wiring = Wiring::load("wiring.toml")?;
+// println!("{}", wiring);  // the resolved wiring table, for a dry run
+//
+// let button = wiring.input_pin("button")?;
+// let laser = wiring.output_pin("laser")?;
+// let converter = wiring.i2c("converter")?;
+// let mut converter = PCF8591::new(&converter.bus, converter.address, 3.3)...;
+// ```
+
+use rppal::{
+    gpio::{ InputPin, OutputPin },
+    pwm::Channel,
+};
+use serde::Deserialize;
+use std::{
+    collections::{ BTreeMap, HashMap },
+    fmt, fs,
+    path::Path,
+};
+use crate::{
+    digital::ActiveLevel,
+    error::{ GpioError, Result },
+    input::{ ConfiguredInput, InputConfig, Pull },
+    pin_id::PinId,
+    registry::{ ClaimedPin, PinMode, PinRegistry },
+};
+
+const DEFAULT_I2C_BUS: &str = "/dev/i2c-1";
+
+// ----------------------------------- file format -----------------------------------------------
+
+// The three ways of giving a pin, spelled out on each pin device so
+// `deny_unknown_fields` also catches typos there (it's ignored through `flatten`).
+struct PinConfig {
+    pin: Option<u8>,
+    header_pin: Option<u8>,
+    wiring_pi: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PullConfig {
+    #[default]
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ActiveConfig {
+    #[default]
+    Low,
+    High,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+enum DeviceConfig {
+    Input {
+        pin: Option<u8>,
+        header_pin: Option<u8>,
+        wiring_pi: Option<u8>,
+        #[serde(default)]
+        pull: PullConfig,
+        #[serde(default)]
+        active: ActiveConfig,
+    },
+    Output {
+        pin: Option<u8>,
+        header_pin: Option<u8>,
+        wiring_pi: Option<u8>,
+    },
+    I2c {
+        bus: Option<String>,
+        address: u16,
+    },
+    Pwm {
+        channel: u8,
+    },
+}
+
+// ----------------------------------- resolved wiring -------------------------------------------
+
+/// Where an I2C device lives.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct I2cAddress {
+    pub bus: String,
+    pub address: u16,
+}
+
+/// One device of the wiring file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Device {
+    Input { pin: PinId, config: InputConfig },
+    Output { pin: PinId },
+    I2c(I2cAddress),
+    Pwm { channel: Channel },
+}
+
+/// The devices of a wiring file, by name.
+#[derive(Debug, Clone)]
+pub struct Wiring {
+    devices: BTreeMap<String, Device>,
+}
+
+impl Wiring {
+    /// Load and validate the wiring file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Wiring> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|error| GpioError::Wiring(format!("can't read `{}`: {}", path.display(), error)))?;
+        Wiring::from_toml_str(&content).map_err(|error| match error {
+            GpioError::Wiring(message) => GpioError::Wiring(format!("`{}`: {}", path.display(), message)),
+            error => error,
+        })
+    }
+
+    /// Parse and validate a wiring file content.
+    pub fn from_toml_str(content: &str) -> Result<Wiring> {
+        let configs: BTreeMap<String, DeviceConfig> = toml::from_str(content)
+            .map_err(|error| GpioError::Wiring(error.to_string()))?;
+
+        let mut devices = BTreeMap::new();
+        for (name, config) in configs {
+            let device = Wiring::resolve(&name, config)?;
+            devices.insert(name, device);
+        }
+
+        let wiring = Wiring { devices };
+        wiring.check_conflicts()?;
+        Ok(wiring)
+    }
+
+    fn resolve(name: &str, config: DeviceConfig) -> Result<Device> {
+        let device = match config {
+            DeviceConfig::Input { pin, header_pin, wiring_pi, pull, active } => {
+                let pull = match pull {
+                    PullConfig::None => Pull::None,
+                    PullConfig::Up => Pull::Up,
+                    PullConfig::Down => Pull::Down,
+                };
+                let active_level = match active {
+                    ActiveConfig::Low => ActiveLevel::Low,
+                    ActiveConfig::High => ActiveLevel::High,
+                };
+                let pin = Wiring::resolve_pin(name, PinConfig { pin, header_pin, wiring_pi })?;
+                Device::Input { pin, config: InputConfig { pull, active_level } }
+            }
+            DeviceConfig::Output { pin, header_pin, wiring_pi } => {
+                Device::Output { pin: Wiring::resolve_pin(name, PinConfig { pin, header_pin, wiring_pi })? }
+            }
+            DeviceConfig::I2c { bus, address } => Device::I2c(I2cAddress {
+                bus: bus.unwrap_or_else(|| DEFAULT_I2C_BUS.to_string()),
+                address,
+            }),
+            DeviceConfig::Pwm { channel } => match channel {
+                0 => Device::Pwm { channel: Channel::Pwm0 },
+                1 => Device::Pwm { channel: Channel::Pwm1 },
+                _ => return Err(GpioError::Wiring(format!("`{}`: PWM channel {} doesn't exist, use 0 or 1", name, channel))),
+            },
+        };
+
+        Ok(device)
+    }
+
+    fn resolve_pin(name: &str, config: PinConfig) -> Result<PinId> {
+        let pin = match (config.pin, config.header_pin, config.wiring_pi) {
+            (Some(bcm), None, None) => PinId::bcm(bcm),
+            (None, Some(physical), None) => PinId::physical(physical),
+            (None, None, Some(wiring_pi)) => PinId::wiring_pi(wiring_pi),
+            _ => return Err(GpioError::Wiring(format!("`{}`: give exactly one of `pin`, `header_pin` or `wiring_pi`", name))),
+        };
+
+        pin.map_err(|error| GpioError::Wiring(format!("`{}`: {}", name, error)))
+    }
+
+    // Claim everything in a throwaway registry, the first conflict is the error.
+    fn check_conflicts(&self) -> Result<()> {
+        let registry = PinRegistry::new();
+        let mut claims = Vec::new();
+        let mut i2c_addresses: HashMap<&I2cAddress, &str> = HashMap::new();
+        let mut i2c1_claimed = false;
+
+        for (name, device) in self.devices.iter() {
+            let claim = match device {
+                Device::Input { pin, .. } => Some(registry.claim(pin.bcm_number(), PinMode::Input, name)),
+                Device::Output { pin } => Some(registry.claim(pin.bcm_number(), PinMode::Output, name)),
+                Device::Pwm { channel } => Some(registry.claim_pwm(*channel, name)),
+                Device::I2c(address) => {
+                    if let Some(other) = i2c_addresses.insert(address, name) {
+                        return Err(GpioError::Wiring(format!(
+                            "`{}` and `{}` are both at address {:#04x} on `{}`",
+                            other, name, address.address, address.bus
+                        )));
+                    }
+
+                    // Devices on the same bus share `SDA`/`SCL`, claim them once
+                    if address.bus == DEFAULT_I2C_BUS && !i2c1_claimed {
+                        i2c1_claimed = true;
+                        Some(registry.claim_i2c(name))
+                    } else {
+                        None
+                    }
+                }
+            };
+
+            if let Some(claim) = claim {
+                claims.push(claim.map_err(|error| GpioError::Wiring(format!("`{}`: {}", name, error)))?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The device called `name`.
+    pub fn device(&self, name: &str) -> Result<&Device> {
+        self.devices.get(name).ok_or_else(|| GpioError::Wiring(format!("no device `{}` in the wiring", name)))
+    }
+
+    /// Every device, ordered by name.
+    pub fn devices(&self) -> impl Iterator<Item = (&str, &Device)> {
+        self.devices.iter().map(|(name, device)| (name.as_str(), device))
+    }
+
+    /// Create the input pin `name`, claimed in `PinRegistry::global()`.
+    pub fn input_pin(&self, name: &str) -> Result<ClaimedPin<ConfiguredInput<InputPin>>> {
+        match self.device(name)? {
+            Device::Input { pin, config } => PinRegistry::global().configured_input_pin(pin.bcm_number(), *config, name),
+            _ => Err(self.wrong_kind(name, "input")),
+        }
+    }
+
+    /// Create the output pin `name`, claimed in `PinRegistry::global()`.
+    pub fn output_pin(&self, name: &str) -> Result<ClaimedPin<OutputPin>> {
+        match self.device(name)? {
+            Device::Output { pin } => PinRegistry::global().output_pin(pin.bcm_number(), name),
+            _ => Err(self.wrong_kind(name, "output")),
+        }
+    }
+
+    /// Bus and address of the I2C device `name`.
+    pub fn i2c(&self, name: &str) -> Result<I2cAddress> {
+        match self.device(name)? {
+            Device::I2c(address) => Ok(address.clone()),
+            _ => Err(self.wrong_kind(name, "i2c")),
+        }
+    }
+
+    /// PWM channel of the device `name`.
+    pub fn pwm_channel(&self, name: &str) -> Result<Channel> {
+        match self.device(name)? {
+            Device::Pwm { channel } => Ok(*channel),
+            _ => Err(self.wrong_kind(name, "pwm")),
+        }
+    }
+
+    fn wrong_kind(&self, name: &str, expected: &str) -> GpioError {
+        GpioError::Wiring(format!("`{}` isn't an `{}` device", name, expected))
+    }
+}
+
+impl fmt::Display for Wiring {
+    /// The resolved wiring table.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<24} {:<7} {:<17} details", "device", "kind", "pin")?;
+        for (name, device) in self.devices.iter() {
+            let (kind, pin, details) = match device {
+                Device::Input { pin, config } => (
+                    "input",
+                    pin.to_string(),
+                    format!("pull {:?}, active {:?}", config.pull, config.active_level),
+                ),
+                Device::Output { pin } => ("output", pin.to_string(), String::new()),
+                Device::I2c(address) => {
+                    // Only the default bus has known pins, others come from an overlay
+                    let pins = if address.bus == DEFAULT_I2C_BUS { "GPIO2/3 (pin 3/5)".to_string() } else { address.bus.clone() };
+                    ("i2c", pins, format!("{:#04x} on {}", address.address, address.bus))
+                }
+                Device::Pwm { channel } => ("pwm", PinId::of_pwm_channel(*channel).to_string(), format!("{:?}", channel)),
+            };
+            let line = format!("{:<24} {:<7} {:<17} {}", name, kind, pin, details);
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const LASER_DEMO: &str = r#"
+        [button]
+        kind = "input"
+        header_pin = 11
+        pull = "up"
+
+        [laser]
+        kind = "output"
+        pin = 27
+
+        [converter]
+        kind = "i2c"
+        address = 0x48
+
+        [gate_servo]
+        kind = "pwm"
+        channel = 0
+    "#;
+
+    #[test]
+    fn wiring_should_resolve_every_device() {
+        let wiring = Wiring::from_toml_str(LASER_DEMO).unwrap();
+
+        assert_eq!(wiring.device("button").unwrap(), &Device::Input {
+            pin: PinId::bcm(17).unwrap(),
+            config: InputConfig { pull: Pull::Up, active_level: ActiveLevel::Low },
+        });
+        assert_eq!(wiring.i2c("converter").unwrap(), I2cAddress { bus: DEFAULT_I2C_BUS.to_string(), address: 0x48 });
+        assert_eq!(wiring.pwm_channel("gate_servo").unwrap(), Channel::Pwm0);
+        assert_eq!(wiring.pwm_channel("laser").is_err(), true);
+        assert_eq!(wiring.to_string().contains("GPIO27 (pin 13)"), true);
+    }
+
+    #[test]
+    fn shared_pins_should_be_rejected() {
+        let status_led_on_pwm0 = format!("{}\n[status_led]\nkind = \"output\"\npin = 18\n", LASER_DEMO);
+        let error = Wiring::from_toml_str(&status_led_on_pwm0).unwrap_err().to_string();
+
+        assert_eq!(error.contains("BCM pin 18"), true);
+        assert_eq!(error.contains("gate_servo"), true);
+    }
+
+    #[test]
+    fn bad_pins_should_be_rejected() {
+        assert_eq!(Wiring::from_toml_str("[led]\nkind = \"output\"\nheader_pin = 6\n").is_err(), true);
+        assert_eq!(Wiring::from_toml_str("[led]\nkind = \"output\"\npin = 17\nheader_pin = 11\n").is_err(), true);
+        assert_eq!(Wiring::from_toml_str("[led]\nkind = \"lamp\"\npin = 17\n").is_err(), true);
+
+        // Typos point at the line
+        let error = Wiring::from_toml_str("[led]\nkind = \"output\"\npin = seventeen\n").unwrap_err().to_string();
+        assert_eq!(error.contains("line 3"), true);
+    }
+
+    #[test]
+    fn misspelled_keys_should_be_rejected() {
+        let path = std::env::temp_dir().join(format!("gpio_util_wiring_{}.toml", std::process::id()));
+        for device in ["kind = \"input\"\npin = 17\npul = \"up\"", "kind = \"input\"\npin = 17\nactve = \"high\"", "kind = \"output\"\npn = 27"].iter() {
+            fs::write(&path, format!("[device]\n{}\n", device)).unwrap();
+            let error = Wiring::load(&path).unwrap_err().to_string();
+            assert_eq!(error.contains("unknown field"), true);
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn other_i2c_buses_should_show_their_bus() {
+        let wiring = Wiring::from_toml_str("[rtc]\nkind = \"i2c\"\nbus = \"/dev/i2c-3\"\naddress = 0x68\n").unwrap();
+        let table = wiring.to_string();
+
+        assert_eq!(table.contains("GPIO2/3"), false);
+        assert_eq!(table.contains("/dev/i2c-3"), true);
+        assert_eq!(Wiring::from_toml_str(LASER_DEMO).unwrap().to_string().contains("GPIO2/3 (pin 3/5)"), true);
+    }
+}
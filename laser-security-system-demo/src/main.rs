@@ -1,79 +1,104 @@
-/// Simulate high-tech laser detecting entry security system demo:
-///
-/// 1. Tap on button to toggle the security system enable or disable.
-///
-/// 2. When then system is enabled, laser emitter and laser light detector are turned on.
-///    So if somebody walks through the laser line which will block the light reaching the
-///    light detector, alarm will be triggered, alarm buzzer will make noise.
-///
-///    When the `alarm_trigger_signal` is `high`, it means the security camera will be turned
-///    on recording and send the alarm signal to police station.
-///
-/// How to connect the circuit: 
-///
-/// Pi               Button   Laser  System Status Alarm    Buzzer      Light Detector  8951
-/// ====             ======   ====== ============= ======   ======      ==============  ====
-/// 5V     ----------  VCC
-/// GND    ----------  GND 
-/// GPIO17 ----------  SIG
-///
-/// GPIO27 -----------------  SIG
-/// 5V     -----------------  VCC 
-/// GND    -----------------  GND 
-///
-/// GPIO19 ------------------------------------------------ SIG(LOW)
-///
-/// GPIO20 --------------------------------------- SIG ---- VCC
-/// GND    --------------------------------------- GND ---- GND
-///
-/// GPIO21 ------------------------- SIG ------------------------------ VCC
-/// GND    ------------------------- GND ------------------------------ GND
-///                                                                     AO  ----------- AIN0
-/// 3.3V   ---------------------------------------------------------------------------- VCC
-/// GND    ---------------------------------------------------------------------------- GND
-/// SCL    ---------------------------------------------------------------------------- SCL
-/// SDA    ---------------------------------------------------------------------------- SDA
-///
-/// You need to run by `sudo` if you see the error below:
-///
-/// `Error: PermissionDenied("/dev/gpiomem")`
-///
+//! Simulate high-tech laser detecting entry security system demo:
+//!
+//! 1. Tap on button to enable the security system, hold the button for 1 second to disable
+//!    it. Taps are ignored while the system is enabled, so an accidental tap can't disarm it.
+//!
+//! 2. When then system is enabled, laser emitter and laser light detector are turned on.
+//!    So if somebody walks through the laser line which will block the light reaching the
+//!    light detector, alarm will be triggered, alarm buzzer will make noise.
+//!
+//!    When the `alarm_trigger_signal` is `high`, it means the security camera will be turned
+//!    on recording and send the alarm signal to police station.
+//!
+//! 3. `Ctrl+C` (`SIGINT`) or `SIGTERM` stops all threads and exits. The laser, the status
+//!    indicator and the alarm trigger go `LOW` and the buzzer goes silent right away, on a
+//!    panic as well, they're registered with their safe levels in `SafeState`.
+//!
+//! How to connect the circuit: 
+//!
+//! Pi               Button   Laser  System Status Alarm    Buzzer      Light Detector  8951
+//! ====             ======   ====== ============= ======   ======      ==============  ====
+//! 5V     ----------  VCC
+//! GND    ----------  GND 
+//! GPIO17 ----------  SIG
+//!
+//! GPIO27 -----------------  SIG
+//! 5V     -----------------  VCC 
+//! GND    -----------------  GND 
+//!
+//! GPIO19 ------------------------------------------------ SIG(LOW)
+//!
+//! GPIO20 --------------------------------------- SIG ---- VCC
+//! GND    --------------------------------------- GND ---- GND
+//!
+//! GPIO21 ------------------------- SIG ------------------------------ VCC
+//! GND    ------------------------- GND ------------------------------ GND
+//!                                                                     AO  ----------- AIN0
+//! 3.3V   ---------------------------------------------------------------------------- VCC
+//! GND    ---------------------------------------------------------------------------- GND
+//! SCL    ---------------------------------------------------------------------------- SCL
+//! SDA    ---------------------------------------------------------------------------- SDA
+//!
+//! The pins above are the default `wiring.toml`, edit it after rewiring instead of the code.
+//! `cargo run -- --dry-run` prints the resolved wiring table and exits, another wiring file
+//! can be given as the first argument.
+//!
+//! You need to run by `sudo` if you see the error below:
+//!
+//! ```text
+//! Error: permission denied on `/dev/gpiomem`, run with `sudo`, ...
+//! ```
+//!
 
 use std::{
-    sync::mpsc::{channel, Sender},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Sender},
+        Arc,
+    },
     thread, 
     time::Duration
 };
-use rppal::gpio::{Result, OutputPin};
-use gpio_util::{GpioUtil, PinState};
+use gpio_util::{
+    ButtonEvent, DebounceConfig, DigitalInput, DigitalOutput, GestureConfig, GestureDetector, GpioError, GpioUtil, I2cAddress,
+    PinRegistry, PinState, Result, SafeState, Wiring,
+};
 use pcf8591::{PCF8591, Pin};
 
 
-const GPIO_BUTTON_SIGNAL_PIN: u8 = 17;
-const GPIO_ALARM_BUZZER_PIN: u8 = 19;
-const GPIO_ALARM_TRIGGER_PIN: u8 = 20;
-const GPIO_SYSTEM_STATUS_INDICATOR_PIN: u8 = 21;
-const GPIO_LASER_SIGNAL_PIN: u8 = 27;
+const DEFAULT_WIRING_FILE: &str = "wiring.toml";
 
 
-///
+/// Events sent to the main loop through the event bus.
 enum SystemEventType {
-    ButtonPressed,
+    ButtonClicked,
+    ButtonLongPressed,
     AlarmHappened
 }
 
 
-/// Button signal thread, it will send `SystemEventType::ButtonPressed` to channel when button
-/// pressed.
-fn start_button_signal_thread(event_sender: Sender<SystemEventType>) -> thread::JoinHandle<()> {
-    let button_signal = GpioUtil::create_input_pin(GPIO_BUTTON_SIGNAL_PIN).unwrap();
-    let thread_loop_duration = Duration::from_millis(50);
+/// Button signal thread, it will send `SystemEventType::ButtonClicked` to channel when button
+/// tapped, and `SystemEventType::ButtonLongPressed` when button held and released. The thread
+/// exits when `shutdown` becomes `true`.
+fn start_button_signal_thread<P>(
+    button_signal: P,
+    event_sender: Sender<SystemEventType>,
+    shutdown: Arc<AtomicBool>
+    ) -> thread::JoinHandle<()>
+where
+    P: DigitalInput + Send + 'static,
+{
+    // No double click here, report the click right after release
+    let gesture_config = GestureConfig { double_click_window: Duration::from_millis(0), ..GestureConfig::default() };
+    let mut gestures = GestureDetector::new(DebounceConfig::default(), gesture_config);
 
     thread::spawn(move || {
-        loop {
-            GpioUtil::block_until_button_pressed(&button_signal);
-            let _ = event_sender.send(SystemEventType::ButtonPressed);
-            thread::sleep(thread_loop_duration);
+        while let Some(event) = gestures.wait_for_event_or_cancelled(&button_signal, &shutdown) {
+            match event {
+                ButtonEvent::Click => { let _ = event_sender.send(SystemEventType::ButtonClicked); }
+                ButtonEvent::LongPress(_) => { let _ = event_sender.send(SystemEventType::ButtonLongPressed); }
+                _ => {}
+            }
         }
     })
 }
@@ -81,20 +106,32 @@ fn start_button_signal_thread(event_sender: Sender<SystemEventType>) -> thread::
 
 /// This thread will keep reading the analog value from `PCF8591 AIN0` pin. When something is blocking
 /// the laser light towards to the light detector, analog value will become `255`, then fire an
-/// alarm to the channel.
-fn start_alarm_checking_thread(event_sender: Sender<SystemEventType>) -> thread::JoinHandle<()> {
+/// alarm to the channel. The thread exits when `shutdown` becomes `true`, or with the error
+/// (after setting `shutdown`) if the converter can't be read anymore.
+fn start_alarm_checking_thread(
+    converter_address: I2cAddress,
+    event_sender: Sender<SystemEventType>,
+    shutdown: Arc<AtomicBool>
+    ) -> Result<thread::JoinHandle<Result<()>>> {
     // - `path`: device slave path (0x48 per default)
     // - `address`: has to be defined as per Table 5.
     // - `v_ref`: is the board voltage (e.g. typically 3.3V on raspberry pi)
-    let mut converter = PCF8591::new("/dev/i2c-1", 0x48, 3.3).unwrap();
+    let I2cAddress { bus, address } = converter_address;
+    let mut converter = PCF8591::new(&bus, address, 3.3).map_err(|error| GpioError::from_i2c(&bus, error))?;
     
     let thread_loop_duration = Duration::from_millis(50);
     let mut laser_light_is_blocking = false;
 
-    thread::spawn(move || {
-        loop {
+    Ok(thread::spawn(move || {
+        while !shutdown.load(Ordering::SeqCst) {
             // Analog value is a byte (0~255)
-            let analog_value = converter.analog_read_byte(Pin::AIN0).unwrap();
+            let analog_value = match converter.analog_read_byte(Pin::AIN0) {
+                Ok(analog_value) => analog_value,
+                Err(error) => {
+                    shutdown.store(true, Ordering::SeqCst);
+                    return Err(GpioError::from_i2c(&bus, error));
+                }
+            };
 
             if !laser_light_is_blocking && analog_value > 20 {
                 laser_light_is_blocking = true;
@@ -108,14 +145,16 @@ fn start_alarm_checking_thread(event_sender: Sender<SystemEventType>) -> thread:
 
             thread::sleep(thread_loop_duration);
         }
-    })
+
+        Ok(())
+    }))
 }
 
-///
+/// Flip the system status, the laser and the status indicator follow the new status.
 fn toggle_security_system(
     already_enbaled: bool, 
-    laser_signal: &mut OutputPin, 
-    system_status_indicator_signal: &mut OutputPin
+    laser_signal: &mut impl DigitalOutput, 
+    system_status_indicator_signal: &mut impl DigitalOutput
     ) {
 
     let enable = !already_enbaled;
@@ -128,22 +167,48 @@ fn toggle_security_system(
     println!("{}", status_desc);
 
     let laser_pin_state = if enable { PinState::High } else { PinState::Low };
-    let system_status_indicator_state = laser_pin_state.clone();
+    let system_status_indicator_state = laser_pin_state;
     GpioUtil::set_pin_state(laser_signal, laser_pin_state, None);
     GpioUtil::set_pin_state(system_status_indicator_signal, system_status_indicator_state, None);
 }
 
 
-///
+/// Entry point, wire up the pins and run the event loop.
 fn main() -> Result<()>  {
+    let mut wiring_file = DEFAULT_WIRING_FILE.to_string();
+    let mut dry_run = false;
+    for arg in std::env::args().skip(1) {
+        if arg == "--dry-run" { dry_run = true; } else { wiring_file = arg; }
+    }
+
+    // Wrong pins or two devices on the same pin fail here, before anything is touched
+    let wiring = Wiring::load(&wiring_file)?;
+    if dry_run {
+        println!("{}", wiring);
+        return Ok(());
+    }
+
     // Create event bus
     let (event_sender, event_bus) = channel();
 
+    // Set by `Ctrl+C` or `SIGTERM` (after the outputs are driven safe), every thread
+    // checks it to exit gracefully.
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let shutdown_signal = shutdown.clone();
+    let safe_state = SafeState::global();
+    safe_state.install_panic_hook();
+    safe_state.install_signal_handler(move || shutdown_signal.store(true, Ordering::SeqCst))?;
+
 
-    let mut laser_signal = GpioUtil::create_output_pin(GPIO_LASER_SIGNAL_PIN).unwrap();
-    let mut system_status_indicator_signal = GpioUtil::create_output_pin(GPIO_SYSTEM_STATUS_INDICATOR_PIN).unwrap();
-    let mut alarm_trigger_signal = GpioUtil::create_output_pin(GPIO_ALARM_TRIGGER_PIN).unwrap();
-    let mut alarm_buzzer_signal = GpioUtil::create_output_pin(GPIO_ALARM_BUZZER_PIN).unwrap();
+    // Every pin is claimed in the registry, so two parts of the demo can't end up on the same pin.
+    let registry = PinRegistry::global();
+    let mut laser_signal = safe_state.register(wiring.output_pin("laser")?, PinState::Low, "laser");
+    let mut system_status_indicator_signal =
+        safe_state.register(wiring.output_pin("system_status_indicator")?, PinState::Low, "system status indicator");
+    let mut alarm_trigger_signal = safe_state.register(wiring.output_pin("alarm_trigger")?, PinState::Low, "alarm trigger");
+    // Active `LOW` buzzer, silent when `HIGH`
+    let mut alarm_buzzer_signal = safe_state.register(wiring.output_pin("alarm_buzzer")?, PinState::High, "alarm buzzer");
+    let _converter_bus = registry.claim_i2c("converter")?;
     
     // Make sure disable the system by default
     toggle_security_system(
@@ -153,25 +218,39 @@ fn main() -> Result<()>  {
     );
 
     println!("\n<<< Laser detecting entry security system is Ready >>>\n");
-    println!("Please tap on the button to toggle the system status.\n");
+    println!("Please tap on the button to enable the system, hold it for 1 second to disable.\n");
 
      
     let mut security_system_is_already_enabled = false;
     let main_loop_duration = Duration::from_millis(100);
 
-    start_button_signal_thread(event_sender.clone());
-    start_alarm_checking_thread(event_sender.clone());
+    let button_signal = wiring.input_pin("button")?;
+    for allocation in registry.allocations() {
+        println!("{}", allocation);
+    }
+
+    let button_thread = start_button_signal_thread(button_signal, event_sender.clone(), shutdown.clone());
+    let alarm_thread = start_alarm_checking_thread(wiring.i2c("converter")?, event_sender.clone(), shutdown.clone())?;
 
-    loop {
+    while !shutdown.load(Ordering::SeqCst) {
         match event_bus.try_recv() {
-            Ok(SystemEventType::ButtonPressed) => {
+            Ok(SystemEventType::ButtonClicked) if !security_system_is_already_enabled => {
                 toggle_security_system(
                     security_system_is_already_enabled, 
                     &mut laser_signal,
                     &mut system_status_indicator_signal
                     );
 
-                security_system_is_already_enabled = !security_system_is_already_enabled;
+                security_system_is_already_enabled = true;
+            },
+            Ok(SystemEventType::ButtonLongPressed) if security_system_is_already_enabled => {
+                toggle_security_system(
+                    security_system_is_already_enabled, 
+                    &mut laser_signal,
+                    &mut system_status_indicator_signal
+                    );
+
+                security_system_is_already_enabled = false;
             },
             Ok(SystemEventType::AlarmHappened) => {
                 println!("Alarm: Unauthorized entry detected......", );
@@ -186,4 +265,10 @@ fn main() -> Result<()>  {
 
         thread::sleep(main_loop_duration);
     }
+
+    println!("\n<<< Laser detecting entry security system is shutting down >>>\n");
+    let _ = button_thread.join();
+
+    // Exit with the converter error if that's why the alarm thread stopped
+    alarm_thread.join().unwrap_or(Err(GpioError::ThreadPanic))
 }
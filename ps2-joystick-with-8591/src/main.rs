@@ -1,33 +1,38 @@
-/// Use PCF8591 (AD/DA converter) to control PS joystick
-///
-/// How to connect the circuit: 
-///
-/// Pi               8591              Joystick       Pi
-/// ====             ====              ========       ====
-/// 3.3V ----------  VCC               +5V      ----- 3.3V
-/// GND  ----------  GND               GND      ----- GND
-/// SDA  ----------  SDA
-/// SCL  ----------  SCL
-///                  AIN0  ----------- VRX
-///                  AIN1  ----------- VRY
-///                  AIN2  ----------- SW
-///
-/// You need to run by `sudo` if you see the error below:
-///
-/// `Error: PermissionDenied("/dev/gpiomem")`
-///
-
-use std::{time::Duration, thread, error::Error};
+//! Use PCF8591 (AD/DA converter) to control PS joystick
+//!
+//! How to connect the circuit: 
+//!
+//! Pi               8591              Joystick       Pi
+//! ====             ====              ========       ====
+//! 3.3V ----------  VCC               +5V      ----- 3.3V
+//! GND  ----------  GND               GND      ----- GND
+//! SDA  ----------  SDA
+//! SCL  ----------  SCL
+//!                  AIN0  ----------- VRX
+//!                  AIN1  ----------- VRY
+//!                  AIN2  ----------- SW
+//!
+//! You need to run by `sudo` if you see the error below:
+//!
+//! ```text
+//! Error: permission denied on `/dev/i2c-1`, run with `sudo`, ...
+//! ```
+
+use std::{time::Duration, thread};
+use gpio_util::{GpioError, Result};
 use pcf8591::{PCF8591, Pin};
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut converter = PCF8591::new("/dev/i2c-1", 0x48, 3.3).unwrap();
+const I2C_BUS: &str = "/dev/i2c-1";
+
+/// Entry point, print the joystick status whenever it leaves the center.
+fn main() -> Result<()> {
+    let mut converter = PCF8591::new(I2C_BUS, 0x48, 3.3).map_err(|error| GpioError::from_i2c(I2C_BUS, error))?;
 
     loop {
         // Analog value is a byte (0~255)
-        let x = converter.analog_read_byte(Pin::AIN0).unwrap();
-        let y = converter.analog_read_byte(Pin::AIN1).unwrap();
-        let z = converter.analog_read_byte(Pin::AIN2).unwrap();
+        let x = converter.analog_read_byte(Pin::AIN0).map_err(|error| GpioError::from_i2c(I2C_BUS, error))?;
+        let y = converter.analog_read_byte(Pin::AIN1).map_err(|error| GpioError::from_i2c(I2C_BUS, error))?;
+        let z = converter.analog_read_byte(Pin::AIN2).map_err(|error| GpioError::from_i2c(I2C_BUS, error))?;
         // println!("x: {}", x);
         // println!("y: {}", y);
         // println!("z: {}", z);
@@ -43,7 +48,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         if z == 0 { status.push_str("Button pressed"); }
 
-        if status.len() > 0 { println!("Joystick status: {}", status);}
+        if !status.is_empty() { println!("Joystick status: {}", status);}
 
         thread::sleep(Duration::from_millis(10));
     }
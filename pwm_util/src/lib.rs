@@ -0,0 +1,247 @@
+// Hardware PWM lib based on `rppal` crate
+//
+// 1. Enable the two PWM channels by adding the setting below to
+//    `/boot/firmware/usercfg.txt` and reboot:
+//
+//    dtoverlay=pwm-2chan
+//
+// 2. After reboot `/sys/class/pwm/pwmchip0/` exists, `Pwm0` is `GPIO18` and
+//    `Pwm1` is `GPIO19` (see `PinId::of_pwm_channel`). Without it
+//    `PwmOutput::open` fails with `GpioError::PwmChipMissing` (which says what
+//    to fix) instead of a bare `NotFound`.
+//
+// `PwmOutput` talks in frequency, duty percent and pulse width, whatever the
+// device datasheet uses:
+//
+// ```rust
+// let mut servo = PwmOutput::open(Channel::Pwm0, "gate servo")?;
+// servo.set_frequency(50.0)?;
+// servo.set_pulse_width_us(1500.0)?;
+// servo.enable()?;
+//
+// let mut fan = PwmOutput::open(Channel::Pwm1, "fan")?;
+// fan.set_frequency(25_000.0)?;
+// fan.set_duty_percent(40.0)?;
+// fan.enable()?;
+// ```
+//
+// Everything except `open` goes through the `PwmBackend` trait, so it can be
+// tested on any machine with `MockPwm`.
+
+mod mock;
+
+pub use mock::MockPwm;
+
+use gpio_util::{ GpioError, PinClaim, PinId, PinRegistry, Result };
+use rppal::pwm::{ Channel, Polarity, Pwm };
+
+/// Frequency `PwmOutput::open` starts with, until `set_frequency` is called.
+pub const DEFAULT_FREQUENCY: f64 = 50.0;
+
+/// What `PwmOutput` needs from a PWM channel, implemented for `rppal`'s `Pwm`
+/// and `MockPwm`.
+pub trait PwmBackend {
+    /// Set both at once, `duty_cycle` is `0.0` to `1.0`.
+    fn set_frequency(&mut self, frequency: f64, duty_cycle: f64) -> Result<()>;
+
+    /// Start the output.
+    fn enable(&mut self) -> Result<()>;
+
+    /// Stop the output, the pin stays `LOW`.
+    fn disable(&mut self) -> Result<()>;
+}
+
+impl PwmBackend for Pwm {
+    fn set_frequency(&mut self, frequency: f64, duty_cycle: f64) -> Result<()> {
+        Pwm::set_frequency(self, frequency, duty_cycle)?;
+        Ok(())
+    }
+
+    fn enable(&mut self) -> Result<()> {
+        Pwm::enable(self)?;
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result<()> {
+        Pwm::disable(self)?;
+        Ok(())
+    }
+}
+
+/// One hardware PWM channel. It starts disabled at `DEFAULT_FREQUENCY` and 0% duty.
+pub struct PwmOutput<B: PwmBackend = Pwm> {
+    backend: B,
+    channel: Channel,
+    frequency: f64,
+    duty_cycle: f64,
+    enabled: bool,
+    // Released on drop, `None` for outputs created by `with_backend`.
+    _claim: Option<PinClaim>,
+}
+
+impl PwmOutput<Pwm> {
+    /// Open `channel` and claim its pin for `owner` in `PinRegistry::global()`.
+    pub fn open(channel: Channel, owner: &str) -> Result<Self> {
+        let claim = PinRegistry::global().claim_pwm(channel, owner)?;
+        let pwm = Pwm::with_frequency(channel, DEFAULT_FREQUENCY, 0.0, Polarity::Normal, false)?;
+
+        let mut output = PwmOutput::with_backend(pwm, channel);
+        output._claim = Some(claim);
+        Ok(output)
+    }
+}
+
+impl<B: PwmBackend> PwmOutput<B> {
+    /// Wrap an already opened channel, e.g. a `MockPwm` in tests.
+    pub fn with_backend(backend: B, channel: Channel) -> Self {
+        PwmOutput { backend, channel, frequency: DEFAULT_FREQUENCY, duty_cycle: 0.0, enabled: false, _claim: None }
+    }
+
+    /// The backend, e.g. to check a `MockPwm`.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// The PWM channel.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// BCM pin of the channel.
+    pub fn gpio_pin(&self) -> u8 {
+        PinId::of_pwm_channel(self.channel).bcm_number()
+    }
+
+    /// Change the frequency, the duty percent stays the same (so the pulse
+    /// width doesn't).
+    pub fn set_frequency(&mut self, frequency: f64) -> Result<()> {
+        if !(frequency > 0.0 && frequency.is_finite()) {
+            return Err(GpioError::InvalidPwmSetting(format!("frequency {}Hz should be above 0", frequency)));
+        }
+
+        self.backend.set_frequency(frequency, self.duty_cycle)?;
+        self.frequency = frequency;
+        Ok(())
+    }
+
+    /// Set the duty cycle, `0.0` to `100.0`.
+    pub fn set_duty_percent(&mut self, percent: f64) -> Result<()> {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(GpioError::InvalidPwmSetting(format!("duty {}% should be in [0, 100]", percent)));
+        }
+
+        self.set_duty_cycle(percent / 100.0)
+    }
+
+    /// Set the duty cycle through the `HIGH` time of one period, in µs.
+    pub fn set_pulse_width_us(&mut self, pulse_width_us: f64) -> Result<()> {
+        let period_us = self.period_us();
+        if !(0.0..=period_us).contains(&pulse_width_us) {
+            return Err(GpioError::InvalidPwmSetting(format!(
+                "pulse width {}µs should be in [0, {}]µs at {}Hz",
+                pulse_width_us, period_us, self.frequency
+            )));
+        }
+
+        self.set_duty_cycle(pulse_width_us / period_us)
+    }
+
+    fn set_duty_cycle(&mut self, duty_cycle: f64) -> Result<()> {
+        self.backend.set_frequency(self.frequency, duty_cycle)?;
+        self.duty_cycle = duty_cycle;
+        Ok(())
+    }
+
+    /// Start the output.
+    pub fn enable(&mut self) -> Result<()> {
+        self.backend.enable()?;
+        self.enabled = true;
+        Ok(())
+    }
+
+    /// Stop the output, e.g. to let a servo go limp.
+    pub fn disable(&mut self) -> Result<()> {
+        self.backend.disable()?;
+        self.enabled = false;
+        Ok(())
+    }
+
+    /// Whether the output is running.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Frequency in Hz.
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// One period in µs.
+    pub fn period_us(&self) -> f64 {
+        1_000_000.0 / self.frequency
+    }
+
+    /// Duty cycle, `0.0` to `100.0`.
+    pub fn duty_percent(&self) -> f64 {
+        self.duty_cycle * 100.0
+    }
+
+    /// `HIGH` time of one period in µs.
+    pub fn pulse_width_us(&self) -> f64 {
+        self.duty_cycle * self.period_us()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn pulse_width_should_become_the_duty_cycle() {
+        let mock = MockPwm::new();
+        let mut servo = PwmOutput::with_backend(mock.clone(), Channel::Pwm0);
+
+        servo.set_frequency(50.0).unwrap();
+        servo.set_pulse_width_us(1500.0).unwrap();
+        assert!(close(mock.duty_cycle(), 0.075));
+        assert!(close(servo.duty_percent(), 7.5));
+
+        // Same duty at 100Hz, half the pulse width
+        servo.set_frequency(100.0).unwrap();
+        assert!(close(mock.frequency(), 100.0));
+        assert!(close(servo.pulse_width_us(), 750.0));
+    }
+
+    #[test]
+    fn out_of_range_settings_should_be_rejected_and_change_nothing() {
+        let mock = MockPwm::new();
+        let mut output = PwmOutput::with_backend(mock.clone(), Channel::Pwm1);
+        output.set_duty_percent(40.0).unwrap();
+
+        assert!(output.set_duty_percent(120.0).is_err());
+        assert!(output.set_pulse_width_us(30_000.0).is_err());
+        assert!(output.set_frequency(0.0).is_err());
+
+        assert!(close(output.duty_percent(), 40.0));
+        assert!(close(mock.duty_cycle(), 0.4));
+        assert_eq!(output.gpio_pin(), 19);
+    }
+
+    #[test]
+    fn enable_and_disable_should_reach_the_backend() {
+        let mock = MockPwm::new();
+        let mut output = PwmOutput::with_backend(mock.clone(), Channel::Pwm0);
+
+        assert!(!mock.is_enabled());
+        output.enable().unwrap();
+        assert!(mock.is_enabled() && output.is_enabled());
+        output.disable().unwrap();
+        assert!(!(mock.is_enabled() || output.is_enabled()));
+
+    }
+}
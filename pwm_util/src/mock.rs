@@ -0,0 +1,71 @@
+// In-memory PWM backend.
+//
+// `MockPwm` doesn't touch `/sys/class/pwm`, clones share the same state, so a
+// test keeps one clone to look at what the code under test (owning another
+// clone) did to the channel.
+
+use gpio_util::Result;
+use std::sync::{ Arc, Mutex };
+use crate::PwmBackend;
+
+#[derive(Debug, Default)]
+struct MockState {
+    frequency: f64,
+    duty_cycle: f64,
+    enabled: bool,
+    // Every duty cycle set, in order.
+    duty_history: Vec<f64>,
+}
+
+/// A PWM channel that only lives in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MockPwm {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockPwm {
+    /// Disabled, 0Hz and 0% duty.
+    pub fn new() -> Self {
+        MockPwm::default()
+    }
+
+    /// Last frequency set.
+    pub fn frequency(&self) -> f64 {
+        self.state.lock().unwrap().frequency
+    }
+
+    /// Last duty cycle set, `0.0` to `1.0`.
+    pub fn duty_cycle(&self) -> f64 {
+        self.state.lock().unwrap().duty_cycle
+    }
+
+    /// Whether the output is running.
+    pub fn is_enabled(&self) -> bool {
+        self.state.lock().unwrap().enabled
+    }
+
+    /// Every duty cycle set so far, in order.
+    pub fn duty_history(&self) -> Vec<f64> {
+        self.state.lock().unwrap().duty_history.clone()
+    }
+}
+
+impl PwmBackend for MockPwm {
+    fn set_frequency(&mut self, frequency: f64, duty_cycle: f64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.frequency = frequency;
+        state.duty_cycle = duty_cycle;
+        state.duty_history.push(duty_cycle);
+        Ok(())
+    }
+
+    fn enable(&mut self) -> Result<()> {
+        self.state.lock().unwrap().enabled = true;
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result<()> {
+        self.state.lock().unwrap().enabled = false;
+        Ok(())
+    }
+}
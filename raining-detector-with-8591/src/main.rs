@@ -1,32 +1,40 @@
-/// Blinking LED demo
-///
-/// How to connect the circuit: 
-///
-/// Pi               8591              LM393(Raining Detector)  Pi
-/// ====             ====              ========                ====
-/// 3.3V ----------  VCC               VCC      -------------- 3.3V
-/// GND  ----------  GND               GND      -------------- GND
-/// SDA  ----------  SDA
-/// SCL  ----------  SCL
-///                  AIN0  ----------- A0
-/// GPIO18 --------------------------- D0
-/// 
-/// You need to run by `sudo` if you see the error below:
-///
-/// `Error: PermissionDenied("/dev/gpiomem")`
+//! Blinking LED demo
+//!
+//! How to connect the circuit: 
+//!
+//! Pi               8591              LM393(Raining Detector)  Pi
+//! ====             ====              ========                ====
+//! 3.3V ----------  VCC               VCC      -------------- 3.3V
+//! GND  ----------  GND               GND      -------------- GND
+//! SDA  ----------  SDA
+//! SCL  ----------  SCL
+//!                  AIN0  ----------- A0
+//! GPIO18 --------------------------- D0
+//! 
+//! You need to run by `sudo` if you see the error below:
+//!
+//! ```text
+//! Error: permission denied on `/dev/gpiomem`, run with `sudo`, ...
+//! ```
 
 use std::{ thread, time::Duration };
-use rppal::gpio::{Result};
-use gpio_util::{GpioUtil};
+use rppal::gpio::Level;
+use gpio_util::{Edge, EdgeInput, GpioError, GpioUtil, Result};
 use pcf8591::{PCF8591, Pin};
 
 
 const GPIO_PIN: u8 = 17;
+const I2C_BUS: &str = "/dev/i2c-1";
 
-///
+/// Entry point, print the raining status whenever it changes.
 fn main() -> Result<()>  {
-    let mut converter = PCF8591::new("/dev/i2c-1", 0x48, 3.3).unwrap();
-    let raining_detector_pin = GpioUtil::create_input_pin(GPIO_PIN).unwrap();
+    let mut converter = PCF8591::new(I2C_BUS, 0x48, 3.3).map_err(|error| GpioError::from_i2c(I2C_BUS, error))?;
+    let mut raining_detector_pin = GpioUtil::create_input_pin(GPIO_PIN)?;
+
+    // The digital output comes from the pin interrupt instead of reading it every loop.
+    // `HIGH` means not raining, `LOW` means raining
+    let raining_flag_changes = raining_detector_pin.subscribe_channel(Edge::Both)?;
+    let mut is_raning_flag = raining_detector_pin.is_low();
      
     let mut status: String = String::new();
     let mut last_raining_flag: bool = false;
@@ -35,17 +43,18 @@ fn main() -> Result<()>  {
     loop {
         status.clear();
 
-        // `HIGH` means not raining, `LOW` means raining
-        let is_raning_flag = raining_detector_pin.is_low();
+        while let Ok(level) = raining_flag_changes.try_recv() {
+            is_raning_flag = level == Level::Low;
+        }
 
         // Analog value is a byte (0~255)
-        let raining_analog_value = converter.analog_read_byte(Pin::AIN0).unwrap();
+        let raining_analog_value = converter.analog_read_byte(Pin::AIN0).map_err(|error| GpioError::from_i2c(I2C_BUS, error))?;
 
         let mut changed = false;
         if last_raining_flag != is_raning_flag || last_raining_analog_value != raining_analog_value { changed = true; }
 
         // Only update and print out when changed
-        if changed == true {
+        if changed {
             last_raining_flag = is_raning_flag;
             last_raining_analog_value = raining_analog_value;
 
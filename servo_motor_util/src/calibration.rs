@@ -0,0 +1,255 @@
+// Per unit servo calibration.
+//
+// The `0.5ms`/`1.5ms`/`2.4ms` SG90 pulses are datasheet values, a real servo
+// hits its end stop (and buzzes) a bit earlier or later. `ServoCalibration`
+// holds the pulses of one unit, its direction and how far the mechanism
+// around it can go:
+//
+// ```rust
+// let gate = ServoCalibration { min_pulse_ms: 0.6, max_pulse_ms: 2.3, max_angle: 60.0, ..Default::default() };
+// sg90_motor.set_calibration(gate)?;
+// sg90_motor.rotate_to_angle(45.0)?;
+// ```
+//
+// `calibrate_with_button` finds the pulses interactively, the servo sweeps
+// slowly and a button press marks each position:
+//
+// 1. Center: press when the horn lines up with the center mark.
+// 2. `+90` end: press as soon as the horn stops moving (or starts buzzing).
+// 3. `-90` end: same on the other side.
+
+use gpio_util::{ ButtonEdge, Clock, DebounceConfig, Debouncer, DigitalInput, WaitLimit };
+use std::time::Duration;
+use crate::{ error::{ Result, ServoError }, servo::ServoSpec };
+
+// Where the sweeps stop turning around, a bit past the datasheet values.
+const SWEEP_MIN_PULSE_MS: f64 = 0.3;
+const SWEEP_MAX_PULSE_MS: f64 = 2.7;
+const CENTER_SWEEP_PULSE_MS: (f64, f64) = (1.2, 1.8);
+// 5µs every 30ms, about 1.5° per 100ms on a SG90
+const SWEEP_STEP_MS: f64 = 0.005;
+const SWEEP_STEP_PERIOD: Duration = Duration::from_millis(30);
+// Pressing at the end stop means the servo is already pushing, back off a bit.
+const END_STOP_BACK_OFF_MS: f64 = 0.02;
+
+/// Pulses, direction and mechanical limits of one servo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "profiles", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "profiles", serde(default))]
+pub struct ServoCalibration {
+    /// Shortest pulse, `+travel_degrees` (`-` when `reversed`), in ms.
+    pub min_pulse_ms: f64,
+    /// Pulse for `0`, in ms.
+    pub center_pulse_ms: f64,
+    /// Longest pulse, `-travel_degrees` (`+` when `reversed`), in ms.
+    pub max_pulse_ms: f64,
+    /// Angle at the shortest and longest pulse, `90` for a 180° servo.
+    pub travel_degrees: f64,
+    /// Swap the `+` and `-` side, e.g. for a servo mounted upside down.
+    pub reversed: bool,
+    /// Smallest angle the mechanism allows, in degrees.
+    pub min_angle: f64,
+    /// Largest angle the mechanism allows, in degrees.
+    pub max_angle: f64,
+}
+
+impl Default for ServoCalibration {
+    /// The SG90 datasheet values, `-90` to `+90`.
+    fn default() -> Self {
+        ServoSpec::SG90.calibration()
+    }
+}
+
+impl ServoCalibration {
+    /// Fail unless the pulses are ordered and the limits are within the travel.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(ServoError::InvalidCalibration(message));
+
+        if !(0.0 < self.min_pulse_ms && self.min_pulse_ms < self.center_pulse_ms && self.center_pulse_ms < self.max_pulse_ms) {
+            return invalid(format!(
+                "pulses should be 0 < min < center < max, got {}ms, {}ms, {}ms",
+                self.min_pulse_ms, self.center_pulse_ms, self.max_pulse_ms
+            ));
+        }
+        if !(self.travel_degrees > 0.0 && self.travel_degrees <= 180.0) {
+            return invalid(format!("travel should be in (0, 180]°, got {}°", self.travel_degrees));
+        }
+        if !(-self.travel_degrees <= self.min_angle && self.min_angle < self.max_angle && self.max_angle <= self.travel_degrees) {
+            return invalid(format!(
+                "limits should be -{travel} <= min < max <= {travel}, got {}° to {}°",
+                self.min_angle,
+                self.max_angle,
+                travel = self.travel_degrees
+            ));
+        }
+        Ok(())
+    }
+
+    /// Pulse width (in ms) for `degrees`, linear between the center and each end.
+    pub fn pulse_width_ms(&self, degrees: f64) -> Result<f64> {
+        if !(self.min_angle..=self.max_angle).contains(&degrees) {
+            return Err(ServoError::AngleOutOfRange { angle: degrees, min: self.min_angle, max: self.max_angle });
+        }
+
+        // Positive angles go towards the short pulse, unless reversed
+        let towards_min_pulse = if self.reversed { -degrees } else { degrees };
+        let pulse_width_ms = if towards_min_pulse >= 0.0 {
+            self.center_pulse_ms - (self.center_pulse_ms - self.min_pulse_ms) * towards_min_pulse / self.travel_degrees
+        } else {
+            self.center_pulse_ms + (self.max_pulse_ms - self.center_pulse_ms) * -towards_min_pulse / self.travel_degrees
+        };
+
+        Ok(pulse_width_ms)
+    }
+}
+
+/// Find the center and end pulses of a servo with one button, see the module
+/// comment. `set_pulse_width_ms` moves the servo. The direction and the
+/// mechanical limits keep their default, edit them afterwards if needed.
+pub fn calibrate_with_button<B, C, S>(button: &B, clock: &C, mut set_pulse_width_ms: S) -> Result<ServoCalibration>
+where
+    B: DigitalInput + ?Sized,
+    C: Clock + ?Sized,
+    S: FnMut(f64) -> gpio_util::Result<()>,
+{
+    let mut debouncer = Debouncer::new(DebounceConfig::default());
+    let started = clock.now();
+    let mut sweep = |from: f64, to: f64| -> Result<f64> {
+        let sweep = Sweep::BackAndForth(from, to);
+        match sweep_until_pressed(button, clock, &mut debouncer, sweep, WaitLimit::forever(), started, &mut set_pulse_width_ms)? {
+            Some(pressed_at) => Ok(pressed_at),
+            // Without a limit only a press ends the sweep
+            None => unreachable!(),
+        }
+    };
+
+    let center_pulse_ms = sweep(CENTER_SWEEP_PULSE_MS.0, CENTER_SWEEP_PULSE_MS.1)?;
+    let min_pulse_ms = sweep(center_pulse_ms, SWEEP_MIN_PULSE_MS)? + END_STOP_BACK_OFF_MS;
+    let max_pulse_ms = sweep(center_pulse_ms, SWEEP_MAX_PULSE_MS)? - END_STOP_BACK_OFF_MS;
+    set_pulse_width_ms(center_pulse_ms)?;
+
+    let calibration = ServoCalibration { min_pulse_ms, center_pulse_ms, max_pulse_ms, ..Default::default() };
+    calibration.validate()?;
+    Ok(calibration)
+}
+
+// Pulses a sweep goes through, from the first to the second.
+pub(crate) enum Sweep {
+    // Turn around at either end until the press.
+    BackAndForth(f64, f64),
+    // Reaching the end without a press is an error.
+    OneWay(f64, f64),
+}
+
+// Move the servo as `sweep` says until the button is pressed, return the pulse
+// at that moment, or `None` once `limit` (counted from `started`) is reached.
+pub(crate) fn sweep_until_pressed<B, C, S>(
+    button: &B,
+    clock: &C,
+    debouncer: &mut Debouncer,
+    sweep: Sweep,
+    limit: WaitLimit,
+    started: Duration,
+    set_pulse_width_ms: &mut S,
+) -> Result<Option<f64>>
+where
+    B: DigitalInput + ?Sized,
+    C: Clock + ?Sized,
+    S: FnMut(f64) -> gpio_util::Result<()>,
+{
+    let (from, to, one_way) = match sweep {
+        Sweep::BackAndForth(from, to) => (from, to, false),
+        Sweep::OneWay(from, to) => (from, to, true),
+    };
+    let (low, high) = if from < to { (from, to) } else { (to, from) };
+    let mut step = if from < to { SWEEP_STEP_MS } else { -SWEEP_STEP_MS };
+    let mut pulse_width_ms = from;
+
+    loop {
+        set_pulse_width_ms(pulse_width_ms)?;
+
+        let step_end = clock.now() + SWEEP_STEP_PERIOD;
+        while clock.now() < step_end {
+            if let Some(ButtonEdge::Pressed) = debouncer.poll(button, clock) {
+                return Ok(Some(pulse_width_ms));
+            }
+            if limit.is_reached(started, clock.now()) {
+                return Ok(None);
+            }
+            clock.sleep(debouncer.config().sample_period);
+        }
+
+        if !(low..=high).contains(&(pulse_width_ms + step)) {
+            if one_way {
+                return Err(ServoError::InvalidCalibration(format!(
+                    "swept from {}ms to {}ms without a press",
+                    from, to
+                )));
+            }
+            step = -step;
+        }
+        pulse_width_ms += step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use gpio_util::{ InputScript, MockGpio };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn calibration_should_map_angles_and_respect_the_limits() {
+        let calibration = ServoCalibration { min_pulse_ms: 0.6, max_pulse_ms: 2.2, min_angle: -45.0, ..Default::default() };
+
+        assert!(close(calibration.pulse_width_ms(90.0).unwrap(), 0.6));
+        assert!(close(calibration.pulse_width_ms(0.0).unwrap(), 1.5));
+        assert!(close(calibration.pulse_width_ms(-45.0).unwrap(), 1.85));
+        assert!(calibration.pulse_width_ms(-60.0).is_err());
+
+        let reversed = ServoCalibration { reversed: true, ..calibration };
+        assert!(close(reversed.pulse_width_ms(45.0).unwrap(), 1.85));
+
+        assert!(ServoCalibration { center_pulse_ms: 2.5, ..Default::default() }.validate().is_err());
+        assert!(ServoCalibration { max_angle: 120.0, ..Default::default() }.validate().is_err());
+
+        // A 270° servo, 135° each side
+        let wide = ServoCalibration { travel_degrees: 135.0, min_angle: -135.0, max_angle: 135.0, ..Default::default() };
+        wide.validate().unwrap();
+        assert!(close(wide.pulse_width_ms(135.0).unwrap(), 0.5));
+        assert!(close(wide.pulse_width_ms(45.0).unwrap(), 1.5 - 1.0 / 3.0));
+    }
+
+    #[test]
+    fn button_presses_should_mark_the_center_and_both_ends() {
+        // Three taps, 600ms apart
+        let gpio = MockGpio::new();
+        let script = InputScript::new()
+            .high_for(ms(600)).low_for(ms(100))
+            .high_for(ms(600)).low_for(ms(100))
+            .high_for(ms(600)).low_for(ms(100))
+            .high();
+        let button = gpio.input_pin(17, script);
+
+        let mut pulses = Vec::new();
+        let calibration = calibrate_with_button(&button, &gpio.clock(), |pulse_width_ms| {
+            pulses.push(pulse_width_ms);
+            Ok(())
+        })
+        .unwrap();
+
+        // About 20 steps of 5µs per sweep
+        assert!(calibration.center_pulse_ms > 1.25 && calibration.center_pulse_ms < 1.35);
+        assert!(calibration.min_pulse_ms < calibration.center_pulse_ms - 0.05);
+        assert!(calibration.max_pulse_ms > calibration.center_pulse_ms + 0.05);
+        assert!(close(*pulses.last().unwrap(), calibration.center_pulse_ms));
+    }
+}
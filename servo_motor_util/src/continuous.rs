@@ -0,0 +1,502 @@
+// Continuous rotation servos.
+//
+// A 360° servo (e.g. FS90R, see `ServoSpec::SG90_CONTINUOUS`) turns the pulse
+// width into a speed: the stop pulse holds still, shorter pulses turn one
+// way and longer ones the other way, faster the further from the stop. Around
+// the stop there's a dead-band where the servo doesn't move at all, and where
+// exactly it sits depends on the unit (and its trim pot).
+// `ContinuousCalibration` holds both, and `speed` skips the dead-band so any
+// speed other than `0.0` turns the wheel:
+//
+// ```rust
+// let output = PwmOutput::open(Channel::Pwm0, "left wheel")?;
+// let mut wheel = ContinuousServo::new(output, ServoSpec::SG90_CONTINUOUS, ContinuousCalibration::default(), ContinuousConfig::default())?;
+//
+// wheel.set_speed(0.5)?;                             // ramps up in the background
+// wheel.run_for(-1.0, Duration::from_secs(2))?;     // ramp, 2s full speed back, ramp to a stop
+// wheel.wait()?;
+// wheel.stop()?;                                     // right now, no ramp
+// ```
+//
+// `calibrate_stop_with_button` finds the stop pulse and the dead-band while
+// the servo sweeps slowly through them:
+//
+// 1. Press as soon as the wheel stops turning.
+// 2. Press as soon as it starts turning again, before the sweep ends (that's
+//    an error, the stop can't be measured).
+//
+// Like the other button waits it takes a `WaitLimit`, and gives `None` when
+// it's reached before the second press.
+
+use gpio_util::{ Clock, DebounceConfig, Debouncer, DigitalInput, WaitLimit };
+use pwm_util::{ PwmBackend, PwmOutput };
+use rppal::pwm::Pwm;
+use std::{
+    sync::{ atomic::{ AtomicBool, Ordering }, Arc, Mutex },
+    thread,
+    time::{ Duration, Instant },
+};
+use crate::{
+    calibration::{ sweep_until_pressed, Sweep },
+    error::{ Result, ServoError },
+    motion::Motion,
+    servo::ServoSpec,
+};
+
+/// Dead-band half width `ContinuousCalibration::from_spec` starts with, in ms.
+pub const DEFAULT_DEAD_BAND_MS: f64 = 0.02;
+
+// Where the stop sweep turns around, the stop of any unit is in there.
+const STOP_SWEEP_PULSE_MS: (f64, f64) = (1.3, 1.7);
+
+/// Pulses, dead-band and direction of one continuous servo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContinuousCalibration {
+    /// Full speed `+1.0` (`-1.0` when `reversed`), in ms.
+    pub min_pulse_ms: f64,
+    /// The servo holds still, in ms.
+    pub stop_pulse_ms: f64,
+    /// Full speed `-1.0` (`+1.0` when `reversed`), in ms.
+    pub max_pulse_ms: f64,
+    /// Pulses closer than this to the stop don't turn the servo, in ms.
+    pub dead_band_ms: f64,
+    /// Swap the directions, e.g. for the wheel on the other side.
+    pub reversed: bool,
+}
+
+impl Default for ContinuousCalibration {
+    /// The `ServoSpec::SG90_CONTINUOUS` datasheet values.
+    fn default() -> Self {
+        ContinuousCalibration::from_spec(&ServoSpec::SG90_CONTINUOUS)
+    }
+}
+
+impl ContinuousCalibration {
+    /// Datasheet pulses of `spec`, with `DEFAULT_DEAD_BAND_MS`.
+    pub fn from_spec(spec: &ServoSpec) -> Self {
+        ContinuousCalibration {
+            min_pulse_ms: spec.min_pulse_ms,
+            stop_pulse_ms: spec.center_pulse_ms,
+            max_pulse_ms: spec.max_pulse_ms,
+            dead_band_ms: DEFAULT_DEAD_BAND_MS,
+            reversed: false,
+        }
+    }
+
+    /// Fail unless the pulses are ordered with the dead-band in between.
+    pub fn validate(&self) -> Result<()> {
+        let ordered = 0.0 < self.min_pulse_ms
+            && self.dead_band_ms >= 0.0
+            && self.min_pulse_ms < self.stop_pulse_ms - self.dead_band_ms
+            && self.stop_pulse_ms + self.dead_band_ms < self.max_pulse_ms;
+
+        if !ordered {
+            return Err(ServoError::InvalidCalibration(format!(
+                "pulses should be 0 < min < stop - dead-band < stop + dead-band < max, got {}ms, {}ms ± {}ms, {}ms",
+                self.min_pulse_ms, self.stop_pulse_ms, self.dead_band_ms, self.max_pulse_ms
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pulse width (in ms) for `speed` (`-1.0` to `1.0`), linear from the edge
+    /// of the dead-band to the full speed pulse.
+    pub fn pulse_width_ms(&self, speed: f64) -> Result<f64> {
+        if !(-1.0..=1.0).contains(&speed) {
+            return Err(ServoError::InvalidMotion(format!("speed {} should be in [-1, 1]", speed)));
+        }
+        if speed == 0.0 {
+            return Ok(self.stop_pulse_ms);
+        }
+
+        // Positive speeds go towards the short pulse, unless reversed
+        let towards_min_pulse = if self.reversed { -speed } else { speed };
+        let pulse_width_ms = if towards_min_pulse > 0.0 {
+            let slowest = self.stop_pulse_ms - self.dead_band_ms;
+            slowest - (slowest - self.min_pulse_ms) * towards_min_pulse
+        } else {
+            let slowest = self.stop_pulse_ms + self.dead_band_ms;
+            slowest + (self.max_pulse_ms - slowest) * -towards_min_pulse
+        };
+
+        Ok(pulse_width_ms)
+    }
+}
+
+/// Ramp settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContinuousConfig {
+    /// Time to go from stop to full speed, changes take their share of it.
+    pub ramp: Duration,
+    /// How often the pulse width is updated while ramping.
+    pub update_period: Duration,
+}
+
+impl Default for ContinuousConfig {
+    /// `300ms` from stop to full speed, updated every PWM period (`20ms`).
+    fn default() -> Self {
+        ContinuousConfig { ramp: Duration::from_millis(300), update_period: Duration::from_millis(20) }
+    }
+}
+
+// What the ramp thread shares with `ContinuousServo`.
+struct Shared<B: PwmBackend> {
+    output: Mutex<PwmOutput<B>>,
+    speed: Mutex<f64>,
+    error: Mutex<Option<ServoError>>,
+}
+
+impl<B: PwmBackend> Shared<B> {
+    fn apply(&self, calibration: &ContinuousCalibration, speed: f64) -> Result<()> {
+        let pulse_width_ms = calibration.pulse_width_ms(speed)?;
+        self.output.lock().unwrap().set_pulse_width_us(pulse_width_ms * 1000.0)?;
+        *self.speed.lock().unwrap() = speed;
+        Ok(())
+    }
+}
+
+/// A continuous rotation servo on a PWM channel, see the module comment.
+pub struct ContinuousServo<B: PwmBackend + Send + 'static = Pwm> {
+    shared: Arc<Shared<B>>,
+    calibration: ContinuousCalibration,
+    config: ContinuousConfig,
+    motion: Option<Motion>,
+}
+
+impl<B: PwmBackend + Send + 'static> ContinuousServo<B> {
+    /// Take over `output`, set it up for `spec` and send the stop pulse.
+    pub fn new(mut output: PwmOutput<B>, spec: ServoSpec, calibration: ContinuousCalibration, config: ContinuousConfig) -> Result<Self> {
+        if !spec.continuous {
+            return Err(ServoError::InvalidCalibration(format!("{} is a positional servo, it has no speed", spec.name)));
+        }
+        calibration.validate()?;
+
+        output.set_frequency(spec.frequency())?;
+        output.set_pulse_width_us(calibration.stop_pulse_ms * 1000.0)?;
+        output.enable()?;
+
+        let shared = Arc::new(Shared { output: Mutex::new(output), speed: Mutex::new(0.0), error: Mutex::new(None) });
+        Ok(ContinuousServo { shared, calibration, config, motion: None })
+    }
+
+    /// Ramp to `speed` (`-1.0` to `1.0`) in the background. A ramp or run in
+    /// progress is cancelled first.
+    pub fn set_speed(&mut self, speed: f64) -> Result<()> {
+        self.start(vec![Leg { speed, hold: Duration::from_secs(0) }])
+    }
+
+    /// Ramp to `speed`, keep it for `duration`, then ramp to a stop, in the background.
+    pub fn run_for(&mut self, speed: f64, duration: Duration) -> Result<()> {
+        self.start(vec![Leg { speed, hold: duration }, Leg { speed: 0.0, hold: Duration::from_secs(0) }])
+    }
+
+    /// Send the stop pulse right away, no ramp.
+    pub fn stop(&mut self) -> Result<()> {
+        self.cancel();
+        self.shared.apply(&self.calibration, 0.0)
+    }
+
+    /// Block until the current ramp or run is done, with its error if it failed.
+    pub fn wait(&mut self) -> Result<()> {
+        if let Some(motion) = self.motion.take() {
+            motion.join();
+        }
+        match self.shared.error.lock().unwrap().take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the current ramp or run, the servo keeps the speed it's at.
+    pub fn cancel(&mut self) {
+        if let Some(motion) = self.motion.take() {
+            motion.cancel();
+        }
+    }
+
+    /// Whether a ramp or run is in progress.
+    pub fn is_busy(&self) -> bool {
+        self.motion.as_ref().is_some_and(|motion| !motion.is_finished())
+    }
+
+    /// Speed of the last pulse sent.
+    pub fn speed(&self) -> f64 {
+        *self.shared.speed.lock().unwrap()
+    }
+
+    /// Calibration in use.
+    pub fn calibration(&self) -> &ContinuousCalibration {
+        &self.calibration
+    }
+
+    fn start(&mut self, legs: Vec<Leg>) -> Result<()> {
+        for leg in legs.iter() {
+            self.calibration.pulse_width_ms(leg.speed)?;
+        }
+        self.cancel();
+        // `wait` reports this run, not an earlier one which failed
+        *self.shared.error.lock().unwrap() = None;
+
+        let shared = self.shared.clone();
+        let calibration = self.calibration;
+        let config = self.config;
+        self.motion = Some(Motion::spawn(move |cancelled| {
+            for leg in legs.iter() {
+                match leg.run(&shared, &calibration, &config, cancelled) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(error) => {
+                        *shared.error.lock().unwrap() = Some(error);
+                        break;
+                    }
+                }
+            }
+        }));
+        Ok(())
+    }
+}
+
+impl<B: PwmBackend + Send + 'static> Drop for ContinuousServo<B> {
+    /// Stop the servo and the pulses.
+    fn drop(&mut self) {
+        self.cancel();
+        let _ = self.shared.output.lock().unwrap().disable();
+    }
+}
+
+// Ramp to `speed`, then keep it for `hold`.
+struct Leg {
+    speed: f64,
+    hold: Duration,
+}
+
+impl Leg {
+    // `false` when cancelled.
+    fn run<B: PwmBackend>(&self, shared: &Shared<B>, calibration: &ContinuousCalibration, config: &ContinuousConfig, cancelled: &AtomicBool) -> Result<bool> {
+        let from = *shared.speed.lock().unwrap();
+        let ramp = config.ramp.mul_f64((self.speed - from).abs());
+        let start = Instant::now();
+
+        loop {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(false);
+            }
+            let time = if ramp.is_zero() { 1.0 } else { (start.elapsed().as_secs_f64() / ramp.as_secs_f64()).min(1.0) };
+            shared.apply(calibration, from + (self.speed - from) * time)?;
+
+            if time >= 1.0 {
+                break;
+            }
+            thread::sleep(config.update_period);
+        }
+
+        let hold_end = Instant::now() + self.hold;
+        while Instant::now() < hold_end {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(false);
+            }
+            thread::sleep(config.update_period.min(hold_end - Instant::now()));
+        }
+
+        Ok(true)
+    }
+}
+
+/// Find the stop pulse and dead-band of a continuous servo with one button,
+/// see the module comment. `set_pulse_width_ms` moves the servo. The full speed
+/// pulses and the direction keep their default. `None` if `limit` is reached
+/// first, the servo is then back at the default stop pulse.
+pub fn calibrate_stop_with_button<B, C, S>(button: &B, limit: WaitLimit, clock: &C, mut set_pulse_width_ms: S) -> Result<Option<ContinuousCalibration>>
+where
+    B: DigitalInput + ?Sized,
+    C: Clock + ?Sized,
+    S: FnMut(f64) -> gpio_util::Result<()>,
+{
+    let mut debouncer = Debouncer::new(DebounceConfig::default());
+    let started = clock.now();
+
+    let first_sweep = Sweep::BackAndForth(STOP_SWEEP_PULSE_MS.0, STOP_SWEEP_PULSE_MS.1);
+    let stops_at = sweep_until_pressed(button, clock, &mut debouncer, first_sweep, limit, started, &mut set_pulse_width_ms)?;
+    // Only upwards, a press on the way back would be below `stops_at`
+    let starts_at = match stops_at {
+        Some(stops_at) => {
+            let second_sweep = Sweep::OneWay(stops_at, STOP_SWEEP_PULSE_MS.1);
+            sweep_until_pressed(button, clock, &mut debouncer, second_sweep, limit, started, &mut set_pulse_width_ms)?
+        }
+        None => None,
+    };
+
+    let (stops_at, starts_at) = match (stops_at, starts_at) {
+        (Some(stops_at), Some(starts_at)) => (stops_at, starts_at),
+        _ => {
+            set_pulse_width_ms(ContinuousCalibration::default().stop_pulse_ms)?;
+            return Ok(None);
+        }
+    };
+
+    let calibration = ContinuousCalibration {
+        stop_pulse_ms: (stops_at + starts_at) / 2.0,
+        dead_band_ms: (starts_at - stops_at) / 2.0,
+        ..Default::default()
+    };
+    set_pulse_width_ms(calibration.stop_pulse_ms)?;
+
+    calibration.validate()?;
+    Ok(Some(calibration))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use gpio_util::{ InputScript, MockGpio };
+    use pwm_util::MockPwm;
+    use rppal::pwm::Channel;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn speeds_should_skip_the_dead_band() {
+        let calibration = ContinuousCalibration { stop_pulse_ms: 1.52, dead_band_ms: 0.02, ..Default::default() };
+
+        assert!(close(calibration.pulse_width_ms(0.0).unwrap(), 1.52));
+        assert!(close(calibration.pulse_width_ms(1.0).unwrap(), 1.0));
+        assert!(close(calibration.pulse_width_ms(-1.0).unwrap(), 2.0));
+        // Just outside the dead-band, not at the stop pulse
+        assert!(close(calibration.pulse_width_ms(0.0001).unwrap(), 1.5 - 0.00005));
+        assert!(close(calibration.pulse_width_ms(-0.5).unwrap(), 1.77));
+        assert!(calibration.pulse_width_ms(1.5).is_err());
+
+        let reversed = ContinuousCalibration { reversed: true, ..calibration };
+        assert!(close(reversed.pulse_width_ms(1.0).unwrap(), 2.0));
+        assert!(ContinuousCalibration { dead_band_ms: 0.6, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn wheel_should_ramp_run_and_stop() {
+        let mock = MockPwm::new();
+        let output = PwmOutput::with_backend(mock.clone(), Channel::Pwm0);
+        let config = ContinuousConfig { ramp: ms(100), update_period: ms(5) };
+        let mut wheel = ContinuousServo::new(output, ServoSpec::SG90_CONTINUOUS, ContinuousCalibration::default(), config).unwrap();
+        assert!(close(mock.duty_cycle(), 1.5 / 20.0) && mock.is_enabled());
+        let setup_steps = mock.duty_history().len();
+
+        wheel.set_speed(1.0).unwrap();
+        wheel.wait().unwrap();
+        let duties = mock.duty_history().split_off(setup_steps);
+        assert!(duties.len() > 5);
+        assert!(duties.windows(2).all(|pair| pair[1] <= pair[0] + 1e-12));
+        assert!(close(mock.duty_cycle(), 1.0 / 20.0));
+
+        // 50ms ramp down to half speed, 50ms at it, 50ms ramp to a stop
+        let start = Instant::now();
+        wheel.run_for(0.5, ms(50)).unwrap();
+        wheel.wait().unwrap();
+        assert!(start.elapsed() >= ms(150));
+        assert!(close(wheel.speed(), 0.0) && close(mock.duty_cycle(), 1.5 / 20.0));
+
+        wheel.set_speed(-1.0).unwrap();
+        wheel.stop().unwrap();
+        assert!(close(wheel.speed(), 0.0) && !wheel.is_busy());
+
+        drop(wheel);
+        assert!(!mock.is_enabled());
+        let output = PwmOutput::with_backend(MockPwm::new(), Channel::Pwm1);
+        assert!(ContinuousServo::new(output, ServoSpec::SG90, ContinuousCalibration::default(), config).is_err());
+    }
+
+    // Fails every call while `failing` is set.
+    struct FlakyPwm {
+        failing: Arc<AtomicBool>,
+    }
+
+    impl PwmBackend for FlakyPwm {
+        fn set_frequency(&mut self, _frequency: f64, _duty_cycle: f64) -> gpio_util::Result<()> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(gpio_util::GpioError::PwmChipMissing);
+            }
+            Ok(())
+        }
+
+        fn enable(&mut self) -> gpio_util::Result<()> {
+            Ok(())
+        }
+
+        fn disable(&mut self) -> gpio_util::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn wait_should_only_report_the_last_run() {
+        let failing = Arc::new(AtomicBool::new(false));
+        let output = PwmOutput::with_backend(FlakyPwm { failing: failing.clone() }, Channel::Pwm0);
+        let config = ContinuousConfig { ramp: ms(20), update_period: ms(5) };
+        let mut wheel = ContinuousServo::new(output, ServoSpec::SG90_CONTINUOUS, ContinuousCalibration::default(), config).unwrap();
+
+        // Fails in the background, nobody waits for it
+        failing.store(true, Ordering::SeqCst);
+        wheel.set_speed(1.0).unwrap();
+        thread::sleep(ms(50));
+        failing.store(false, Ordering::SeqCst);
+
+        wheel.set_speed(0.5).unwrap();
+        wheel.wait().unwrap();
+        assert!(close(wheel.speed(), 0.5));
+    }
+
+    #[test]
+    fn button_presses_should_mark_both_edges_of_the_dead_band() {
+        // Two taps, 600ms apart
+        let gpio = MockGpio::new();
+        let script = InputScript::new()
+            .high_for(ms(600)).low_for(ms(100))
+            .high_for(ms(600)).low_for(ms(100))
+            .high();
+        let button = gpio.input_pin(17, script);
+
+        let mut pulses = Vec::new();
+        let calibration = calibrate_stop_with_button(&button, WaitLimit::forever(), &gpio.clock(), |pulse_width_ms| {
+            pulses.push(pulse_width_ms);
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+
+        // About 20 steps of 5µs per sweep, from 1.3ms upwards
+        assert!(calibration.stop_pulse_ms > 1.35 && calibration.stop_pulse_ms < 1.5);
+        assert!(calibration.dead_band_ms > 0.02 && calibration.dead_band_ms < 0.1);
+        assert!(close(*pulses.last().unwrap(), calibration.stop_pulse_ms));
+    }
+
+    #[test]
+    fn late_or_missing_presses_should_not_give_a_calibration() {
+        // The second tap comes after the one-way sweep reached 1.7ms (about 1.8s)
+        let gpio = MockGpio::new();
+        let script = InputScript::new()
+            .high_for(ms(600)).low_for(ms(100))
+            .high_for(ms(3000)).low_for(ms(100))
+            .high();
+        let button = gpio.input_pin(17, script);
+        let late = calibrate_stop_with_button(&button, WaitLimit::forever(), &gpio.clock(), |_| Ok(()));
+        assert!(matches!(late, Err(ServoError::InvalidCalibration(_))));
+
+        // One tap only, then the time is up
+        let gpio = MockGpio::new();
+        let button = gpio.input_pin(17, InputScript::new().high_for(ms(600)).low_for(ms(100)).high());
+        let mut pulses = Vec::new();
+        let timed_out = calibrate_stop_with_button(&button, WaitLimit::timeout(ms(1500)), &gpio.clock(), |pulse_width_ms| {
+            pulses.push(pulse_width_ms);
+            Ok(())
+        });
+        assert_eq!(timed_out.unwrap(), None);
+        assert!(gpio.clock().now() < ms(1600));
+        assert!(close(*pulses.last().unwrap(), 1.5));
+    }
+}
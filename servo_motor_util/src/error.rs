@@ -0,0 +1,84 @@
+// Errors of `servo_motor_util`.
+//
+// Pin and PWM problems stay `GpioError`s, wrapped in `ServoError::Gpio` so they
+// convert with `?`. The other variants are about the servo itself:
+//
+// ```rust
+// match sg90_motor.rotate_to_angle(120.0) {
+//     Err(ServoError::AngleOutOfRange { max, .. }) => sg90_motor.rotate_to_angle(max)?,
+//     result => result?,
+// }
+// ```
+
+use gpio_util::GpioError;
+use std::{ error, fmt, io };
+
+/// `Result` of `servo_motor_util`.
+pub type Result<T> = std::result::Result<T, ServoError>;
+
+/// Everything which can go wrong when driving a servo.
+pub enum ServoError {
+    /// The PWM output (or the calibration button) failed.
+    Gpio(GpioError),
+    /// Servo angle outside of what the servo (or its limits) allows, in degrees.
+    AngleOutOfRange { angle: f64, min: f64, max: f64 },
+    /// Servo calibration doesn't make sense, or the profiles file can't be read.
+    InvalidCalibration(String),
+    /// Motion settings which can't be followed, e.g. a trapezoidal ramp over half the move.
+    InvalidMotion(String),
+}
+
+impl From<GpioError> for ServoError {
+    fn from(error: GpioError) -> Self {
+        ServoError::Gpio(error)
+    }
+}
+
+impl From<io::Error> for ServoError {
+    fn from(error: io::Error) -> Self {
+        ServoError::Gpio(GpioError::Io(error))
+    }
+}
+
+impl fmt::Display for ServoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServoError::Gpio(error) => fmt::Display::fmt(error, f),
+            ServoError::AngleOutOfRange { angle, min, max } => {
+                write!(f, "angle {}° is out of range, the servo goes from {}° to {}°", angle, min, max)
+            }
+            ServoError::InvalidCalibration(message) => write!(f, "invalid servo calibration: {}", message),
+            ServoError::InvalidMotion(message) => write!(f, "invalid motion: {}", message),
+        }
+    }
+}
+
+impl fmt::Debug for ServoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl error::Error for ServoError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ServoError::Gpio(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn gpio_errors_should_keep_their_message() {
+        let error = ServoError::from(GpioError::PwmChipMissing);
+
+        assert!(matches!(error, ServoError::Gpio(GpioError::PwmChipMissing)));
+        assert_eq!(error.to_string(), GpioError::PwmChipMissing.to_string());
+        assert!(error::Error::source(&error).is_some());
+    }
+}
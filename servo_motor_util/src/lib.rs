@@ -1,97 +1,274 @@
 // #![allow(warnings)]
 
-/// 1. How `PWM` workd?
-///    `PWM` stands for `Pulse-width Modulation`. The simple understanding is that: 
-///    Changing the `duty-cycle` to control how much percent of `set to high` (turn on) in a given
-///    time for getting the particular working voltage. For example, if your PWM device supply
-///    totally `5V`, `0 ~ 100%` duty cycle map to `0 ~ 5V`.
-///
-/// 2. First, you need to enable the PWM functionality by add the setting below to `/boot/firmware/usercfg.txt` and reboot:
-/// dtoverlay=pwm-2chan
-///
-/// 3. After reboot, you will be able to see `/sys/class/pwm/pwmchip0/` folder there. Otherwise,
-///    you will get `NotFound` error when u call `Pwm::with_frequency()`.
+// 1. How `PWM` workd?
+//    `PWM` stands for `Pulse-width Modulation`. The simple understanding is that:
+//    Changing the `duty-cycle` to control how much percent of `set to high` (turn on) in a given
+//    time for getting the particular working voltage. For example, if your PWM device supply
+//    totally `5V`, `0 ~ 100%` duty cycle map to `0 ~ 5V`.
+//
+// 2. First, you need to enable the PWM functionality by add the setting below to `/boot/firmware/usercfg.txt` and reboot:
+//    `dtoverlay=pwm-2chan`
+//
+// 3. After reboot, you will be able to see `/sys/class/pwm/pwmchip0/` folder there. Otherwise,
+//    you will get `ServoError::Gpio(GpioError::PwmChipMissing)` when u call `get_motor_with_init_position()`.
+//
+//    Errors are `ServoError`s, pin and PWM ones are wrapped `GpioError`s, see `error.rs`.
+//
+// 4. The SG90 angle is set by the pulse width: `0.5ms` is `+90`, `1.5ms` is `0` and `2.4ms`
+//    is `-90`. Any angle in between is a straight line between those points:
+//
+//    ```rust
+//    let mut sg90_motor = SG90ServoMotor::get_motor_with_init_position(SG90ServoMotorInitPosition::Init, false)?;
+//    sg90_motor.rotate_to_angle(45.0)?;   // 1.0ms
+//    sg90_motor.rotate_to_angle(-30.0)?;  // 1.8ms
+//    sg90_motor.detach()?;                // no more pulses, no more holding torque
+//    ```
+//
+// 5. Those are datasheet values, see `calibration.rs` for calibrating each servo, and
+//    `profiles.rs` (feature `profiles`) for saving the calibrations.
+//
+// 6. `SmoothServo` (see `motion.rs`) moves gradually instead of jumping to the new angle.
+//
+// 7. Other models (MG90S, MG996R, continuous rotation) have their own `ServoSpec`, and
+//    `PwmServo` drives any of them through the `Servo` trait, see `servo.rs`.
+//
+// 8. Continuous rotation servos take a speed instead of an angle, `ContinuousServo` (see
+//    `continuous.rs`) drives them.
 
-use std::{thread, time::Duration};
-use rppal::{
-    pwm::{Channel, Polarity, Pwm, Result},
+mod calibration;
+mod continuous;
+mod error;
+mod motion;
+#[cfg(feature = "profiles")]
+mod profiles;
+mod servo;
+
+pub use calibration::{calibrate_with_button, ServoCalibration};
+pub use continuous::{
+    calibrate_stop_with_button, ContinuousCalibration, ContinuousConfig, ContinuousServo, DEFAULT_DEAD_BAND_MS,
 };
+pub use error::{Result, ServoError};
+pub use motion::{Easing, MotionConfig, SmoothServo};
+#[cfg(feature = "profiles")]
+pub use profiles::CalibrationProfiles;
+pub use servo::{PwmServo, Servo, ServoSpec};
+
+use std::{thread, time::Duration};
+use gpio_util::GpioError;
+use pwm_util::{PwmBackend, PwmOutput};
+use rppal::pwm::{Channel, Pwm};
 
 
 // ----------------------------------- SG90 Servo Motor ------------------------------------------
+// The numbers behind `ServoSpec::SG90`
 const SG90_MOTOR_PERIOD_MS: f64 = 20f64; // Unit in `ms`
-const SG90_MOTOR_FREQUENCE: f64 = 50f64; // Unit in `hertz (Hz)`
 
 // For rotate to the position `0`, it needs `1.5 ms puls` which means `1.5 / 20` = 7.5% duty cycle
-const SG90_MOTOR_INIT_POSITION_DUTY_CYCLE: f64 = 1.5 / SG90_MOTOR_PERIOD_MS;
+const SG90_MOTOR_INIT_POSITION_PULSE_MS: f64 = 1.5;
 
 // For rotate to the position `-90`, it needs `2.4 ms puls` which means `2.4 / 20` = 12% duty cycle
-const SG90_MOTOR_NEGATIVE_90_DEGREE_DUTY_CYCLE: f64 = 2.4 / SG90_MOTOR_PERIOD_MS; // 
+const SG90_MOTOR_NEGATIVE_90_DEGREE_PULSE_MS: f64 = 2.4;
 
 // For rotate to the position `90`, it needs `0.5 ms puls` which means `0.5 / 20` = 2.5% duty cycle
-const SG90_MOTOR_POSITIVE_90_DEGREE_DUTY_CYCLE: f64 = 0.5 / SG90_MOTOR_PERIOD_MS;
+const SG90_MOTOR_POSITIVE_90_DEGREE_PULSE_MS: f64 = 0.5;
 
-///
+/// Smallest angle `rotate_to_angle` accepts, in degrees.
+pub const SG90_MOTOR_MIN_ANGLE: f64 = -90f64;
+
+/// Largest angle `rotate_to_angle` accepts, in degrees.
+pub const SG90_MOTOR_MAX_ANGLE: f64 = 90f64;
+
+/// Where the motor goes right after it's created.
 pub enum SG90ServoMotorInitPosition {
    Init,
    Negative90Degree,
    Positive90Degree
 }
 
-///
-pub struct SG90ServoMotor {
+/// SG90 servo motor, it owns its PWM channel and remembers its angle. The
+/// pulses stop on `detach()` or when it's dropped.
+pub struct SG90ServoMotor<B: PwmBackend = Pwm> {
+    servo: PwmServo<B>,
 }
 
-///
-impl SG90ServoMotor {
+impl SG90ServoMotor<Pwm> {
 
     /// By default, use `Channel::Pwm0` which is the `GPIO18` on the board
     /// If `use_pwm1` set to `true`, then use `Channel::Pwm1` which is then
     /// `GPIO19` on the board.
-    pub fn get_motor_with_init_position(init_position: SG90ServoMotorInitPosition, use_pwm1: bool) -> Result<Pwm> {
-        let mut sg90_motor = Pwm::with_frequency(
-            if use_pwm1 { Channel::Pwm1 } else { Channel::Pwm0 },
-            SG90_MOTOR_FREQUENCE,
-            SG90_MOTOR_INIT_POSITION_DUTY_CYCLE,
-            Polarity::Normal,
-            true
-        );
-
-        if sg90_motor.is_err() { return sg90_motor; }
+    pub fn get_motor_with_init_position(init_position: SG90ServoMotorInitPosition, use_pwm1: bool) -> Result<Self> {
+        let channel = if use_pwm1 { Channel::Pwm1 } else { Channel::Pwm0 };
+        let output = PwmOutput::open(channel, "SG90 servo")?;
+        let mut sg90_motor = SG90ServoMotor::with_output(output, ServoCalibration::default(), SG90ServoMotorInitPosition::Init)?;
 
         // Give enough motor enough time to rotate to the init position.
         thread::sleep(Duration::from_millis(250));
 
-        match init_position {
-            SG90ServoMotorInitPosition::Init => {},
-            SG90ServoMotorInitPosition::Negative90Degree => { let _ = sg90_motor.as_mut().unwrap().set_duty_cycle(SG90_MOTOR_NEGATIVE_90_DEGREE_DUTY_CYCLE); }
-            SG90ServoMotorInitPosition::Positive90Degree => { let _ = sg90_motor.as_mut().unwrap().set_duty_cycle(SG90_MOTOR_POSITIVE_90_DEGREE_DUTY_CYCLE); }
+        sg90_motor.rotate_to_position(init_position)?;
+        Ok(sg90_motor)
+    }
+
+    /// Pulse width (in `ms`) for `degrees`, `+90` is `0.5ms`, `0` is `1.5ms`, `-90` is `2.4ms`.
+    pub fn angle_to_pulse_width_ms(degrees: f64) -> Result<f64> {
+        ServoSpec::SG90.calibration().pulse_width_ms(degrees)
+    }
+}
+
+impl<B: PwmBackend> SG90ServoMotor<B> {
+
+    /// Take over `output` (e.g. a `MockPwm` one in tests) and go to `init_position` right away.
+    pub fn with_output(output: PwmOutput<B>, calibration: ServoCalibration, init_position: SG90ServoMotorInitPosition) -> Result<Self> {
+        let start_angle = match init_position {
+            SG90ServoMotorInitPosition::Init => 0.0,
+            SG90ServoMotorInitPosition::Negative90Degree => calibration.min_angle,
+            SG90ServoMotorInitPosition::Positive90Degree => calibration.max_angle,
+        };
+        let servo = PwmServo::new(output, ServoSpec::SG90, calibration, start_angle)?;
+        Ok(SG90ServoMotor { servo })
+    }
+
+    /// Use the pulses and limits of this particular servo from now on, the
+    /// motor goes back within the new limits if it's outside them.
+    pub fn set_calibration(&mut self, calibration: ServoCalibration) -> Result<()> {
+        self.servo.set_calibration(calibration)
+    }
+
+    /// Rotate to any angle between `-90` and `+90` (or the calibration limits).
+    pub fn rotate_to_angle(&mut self, degrees: f64) -> Result<()> {
+        self.servo.rotate_to_angle(degrees)
+    }
+
+    /// Angle of the last `rotate_*` call, in degrees.
+    pub fn current_angle(&self) -> f64 {
+        self.servo.current_angle()
+    }
+
+    /// Send `pulse_width_ms` pulses, e.g. while calibrating. `current_angle`
+    /// doesn't follow, the pulse may not match any calibrated angle.
+    pub fn set_pulse_width_ms(&mut self, pulse_width_ms: f64) -> Result<()> {
+        if !(0.0..=SG90_MOTOR_PERIOD_MS).contains(&pulse_width_ms) {
+            return Err(GpioError::InvalidPwmSetting(format!(
+                "pulse width {}ms should be in [0, {}]ms",
+                pulse_width_ms, SG90_MOTOR_PERIOD_MS
+            ))
+            .into());
+        }
+
+        self.servo.output_mut().set_pulse_width_us(pulse_width_ms * 1000.0)?;
+        Ok(())
+    }
+
+    /// Rotate to `0`.
+    pub fn rotate_to_init_position(&mut self) -> Result<()> {
+        self.rotate_to_angle(0.0)
+    }
+
+    /// Rotate to `-90` (or the smallest angle of the calibration).
+    pub fn rotate_to_negative_position(&mut self) -> Result<()> {
+        let min_angle = self.servo.calibration().min_angle;
+        self.rotate_to_angle(min_angle)
+    }
+
+    /// Rotate to `+90` (or the largest angle of the calibration).
+    pub fn rotate_to_positive_position(&mut self) -> Result<()> {
+        let max_angle = self.servo.calibration().max_angle;
+        self.rotate_to_angle(max_angle)
+    }
+
+    fn rotate_to_position(&mut self, position: SG90ServoMotorInitPosition) -> Result<()> {
+        match position {
+            SG90ServoMotorInitPosition::Init => self.rotate_to_init_position(),
+            SG90ServoMotorInitPosition::Negative90Degree => self.rotate_to_negative_position(),
+            SG90ServoMotorInitPosition::Positive90Degree => self.rotate_to_positive_position(),
         }
-        // }
+    }
+
+    /// Stop the pulses, the motor stops holding torque (and can be turned by
+    /// hand) until the next `rotate_*` call.
+    pub fn detach(&mut self) -> Result<()> {
+        self.servo.detach()
+    }
+
+    /// Whether the motor gets pulses (and holds its position).
+    pub fn is_attached(&self) -> bool {
+        self.servo.is_attached()
+    }
+
+    /// The PWM output, e.g. to check a `MockPwm`.
+    pub fn output(&self) -> &PwmOutput<B> {
+        self.servo.output()
+    }
+}
 
-        sg90_motor
+impl<B: PwmBackend> Servo for SG90ServoMotor<B> {
+    fn spec(&self) -> &ServoSpec {
+        self.servo.spec()
     }
 
-    ///
-    pub fn rotate_to_init_position(motor: &mut Result<Pwm>) {
-        let _ = motor.as_mut().unwrap().set_duty_cycle(SG90_MOTOR_INIT_POSITION_DUTY_CYCLE); 
+    fn calibration(&self) -> &ServoCalibration {
+        self.servo.calibration()
     }
 
-    ///
-    pub fn rotate_to_negative_position(motor: &mut Result<Pwm>) {
-        let _ = motor.as_mut().unwrap().set_duty_cycle(SG90_MOTOR_NEGATIVE_90_DEGREE_DUTY_CYCLE); 
+    fn current_angle(&self) -> f64 {
+        self.servo.current_angle()
     }
 
-    ///
-    pub fn rotate_to_positive_position(motor: &mut Result<Pwm>) {
-        let _ = motor.as_mut().unwrap().set_duty_cycle(SG90_MOTOR_POSITIVE_90_DEGREE_DUTY_CYCLE); 
+    fn rotate_to_angle(&mut self, degrees: f64) -> Result<()> {
+        self.servo.rotate_to_angle(degrees)
     }
 }
 
 #[cfg(test)]
 mod tests {
+
+    use super::*;
+    use pwm_util::MockPwm;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn angles_should_map_onto_the_datasheet_pulse_widths() {
+        assert!(close(SG90ServoMotor::angle_to_pulse_width_ms(90.0).unwrap(), 0.5));
+        assert!(close(SG90ServoMotor::angle_to_pulse_width_ms(0.0).unwrap(), 1.5));
+        assert!(close(SG90ServoMotor::angle_to_pulse_width_ms(-90.0).unwrap(), 2.4));
+        assert!(close(SG90ServoMotor::angle_to_pulse_width_ms(45.0).unwrap(), 1.0));
+        assert!(close(SG90ServoMotor::angle_to_pulse_width_ms(-30.0).unwrap(), 1.8));
+    }
+
+    #[test]
+    fn motor_should_remember_its_angle_and_let_go_on_detach_or_drop() {
+        let mock = MockPwm::new();
+        let output = PwmOutput::with_backend(mock.clone(), Channel::Pwm0);
+        let mut sg90_motor = SG90ServoMotor::with_output(output, ServoCalibration::default(), SG90ServoMotorInitPosition::Negative90Degree).unwrap();
+        assert!(close(sg90_motor.current_angle(), -90.0) && close(mock.duty_cycle(), 2.4 / 20.0));
+
+        sg90_motor.rotate_to_angle(45.0).unwrap();
+        assert!(sg90_motor.rotate_to_angle(120.0).is_err());
+        assert!(close(sg90_motor.current_angle(), 45.0) && close(mock.duty_cycle(), 1.0 / 20.0));
+
+        sg90_motor.detach().unwrap();
+        assert!(!mock.is_enabled() && !sg90_motor.is_attached());
+        sg90_motor.rotate_to_positive_position().unwrap();
+        assert!(mock.is_enabled() && close(sg90_motor.current_angle(), 90.0));
+
+        drop(sg90_motor);
+        assert!(!mock.is_enabled());
+    }
+
+    #[test]
+    fn out_of_range_angles_should_be_rejected() {
+        for angle in [90.5, -91.0, f64::NAN].iter() {
+            match SG90ServoMotor::angle_to_pulse_width_ms(*angle) {
+                Err(ServoError::AngleOutOfRange { min, max, .. }) => assert!(close(min, -90.0) && close(max, 90.0)),
+                other => panic!("expected `AngleOutOfRange`, got {:?}", other),
+            }
+        }
+    }
 }
@@ -0,0 +1,402 @@
+// Smooth servo motion.
+//
+// Jumping straight to a new pulse width swings the servo at full speed, which
+// slams whatever it moves and pulls enough current to brown out the 5V rail.
+// `SmoothServo` moves through intermediate angles from a background thread
+// instead, following an `Easing` curve, and never faster than
+// `MotionConfig::max_degrees_per_second`:
+//
+// ```rust
+// let output = PwmOutput::open(Channel::Pwm0, "gate servo")?;
+// let servo = PwmServo::new(output, ServoSpec::SG90, ServoCalibration::default(), 0.0)?;
+// let mut gate = SmoothServo::new(servo, MotionConfig::default());
+//
+// gate.move_to(90.0, Duration::from_secs(2), Easing::EaseInOut)?;
+// gate.wait()?;
+//
+// gate.move_to(0.0, Duration::from_secs(2), Easing::Trapezoidal { ramp: 0.25 })?;
+// gate.cancel();  // stops where it is
+// ```
+//
+// It wraps any `Servo`, and is one itself: `rotate_to_angle` moves at the
+// speed limit and waits.
+
+use std::{
+    f64::consts::PI,
+    sync::{
+        atomic::{ AtomicBool, Ordering },
+        Arc, Mutex,
+    },
+    thread,
+    time::{ Duration, Instant },
+};
+use crate::{
+    calibration::ServoCalibration,
+    error::{ Result, ServoError },
+    servo::{ Servo, ServoSpec },
+};
+
+/// Velocity profile of a move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+    /// Slow start and stop, cosine shaped.
+    EaseInOut,
+    /// Constant acceleration for `ramp` of the time, cruise, then constant
+    /// deceleration for `ramp` of the time. `ramp` is in `(0.0, 0.5]`.
+    Trapezoidal { ramp: f64 },
+}
+
+impl Easing {
+    /// How far along the move is (`0.0` to `1.0`) at `time` (`0.0` to `1.0`).
+    pub fn progress(&self, time: f64) -> f64 {
+        let time = time.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => time,
+            Easing::EaseInOut => (1.0 - (PI * time).cos()) / 2.0,
+            Easing::Trapezoidal { ramp } => {
+                let cruise_speed = 1.0 / (1.0 - ramp);
+                if time < ramp {
+                    cruise_speed * time * time / (2.0 * ramp)
+                } else if time <= 1.0 - ramp {
+                    cruise_speed * (time - ramp / 2.0)
+                } else {
+                    1.0 - cruise_speed * (1.0 - time) * (1.0 - time) / (2.0 * ramp)
+                }
+            }
+        }
+    }
+
+    // Top speed of the curve compared to the average speed of the move.
+    fn peak_speed_factor(&self) -> f64 {
+        match *self {
+            Easing::Linear => 1.0,
+            Easing::EaseInOut => PI / 2.0,
+            Easing::Trapezoidal { ramp } => 1.0 / (1.0 - ramp),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match *self {
+            Easing::Trapezoidal { ramp } if !(ramp > 0.0 && ramp <= 0.5) => {
+                Err(ServoError::InvalidMotion(format!("trapezoidal ramp {} should be in (0, 0.5]", ramp)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Motion settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionConfig {
+    /// Moves which would be faster than this (at their peak) take longer instead.
+    pub max_degrees_per_second: Option<f64>,
+    /// How often the pulse width is updated, no point going below one PWM period.
+    pub update_period: Duration,
+}
+
+impl MotionConfig {
+    fn validate(&self) -> Result<()> {
+        match self.max_degrees_per_second {
+            Some(max_speed) if max_speed.is_nan() || max_speed <= 0.0 => {
+                Err(ServoError::InvalidMotion(format!("speed limit {}°/s should be above 0, or `None` for no limit", max_speed)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for MotionConfig {
+    /// At most 180°/s, updated every PWM period (`20ms`).
+    fn default() -> Self {
+        MotionConfig { max_degrees_per_second: Some(180.0), update_period: Duration::from_millis(20) }
+    }
+}
+
+// What the motion thread shares with `SmoothServo`.
+struct Shared<S: Servo> {
+    servo: Mutex<S>,
+    error: Mutex<Option<ServoError>>,
+}
+
+// A background job, cancelled through the flag it gets.
+pub(crate) struct Motion {
+    cancelled: Arc<AtomicBool>,
+    worker: thread::JoinHandle<()>,
+}
+
+impl Motion {
+    pub(crate) fn spawn<F: FnOnce(&AtomicBool) + Send + 'static>(job: F) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let cancelled = cancelled.clone();
+            thread::spawn(move || job(&cancelled))
+        };
+        Motion { cancelled, worker }
+    }
+
+    // Block until the job is done.
+    pub(crate) fn join(self) {
+        let _ = self.worker.join();
+    }
+
+    // Ask the job to stop and wait for it.
+    pub(crate) fn cancel(self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.join();
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.worker.is_finished()
+    }
+}
+
+/// A servo which moves smoothly, see the module comment.
+pub struct SmoothServo<S: Servo + Send + 'static> {
+    shared: Arc<Shared<S>>,
+    spec: ServoSpec,
+    calibration: ServoCalibration,
+    config: MotionConfig,
+    motion: Option<Motion>,
+}
+
+impl<S: Servo + Send + 'static> SmoothServo<S> {
+    /// Take over `servo`, it starts from where it is.
+    pub fn new(servo: S, config: MotionConfig) -> Self {
+        let spec = *servo.spec();
+        let calibration = *servo.calibration();
+        let shared = Arc::new(Shared { servo: Mutex::new(servo), error: Mutex::new(None) });
+        SmoothServo { shared, spec, calibration, config, motion: None }
+    }
+
+    /// Start moving to `angle` in `duration` (longer if that's faster than the
+    /// speed limit). A move in progress is cancelled first.
+    pub fn move_to(&mut self, angle: f64, duration: Duration, easing: Easing) -> Result<()> {
+        self.calibration.pulse_width_ms(angle)?;
+        easing.validate()?;
+        self.config.validate()?;
+        self.cancel();
+        // `wait` reports this move, not an earlier one which failed
+        *self.shared.error.lock().unwrap() = None;
+
+        let from = self.current_angle();
+        let duration = match self.config.max_degrees_per_second {
+            Some(max_speed) => {
+                let shortest = Duration::from_secs_f64((angle - from).abs() * easing.peak_speed_factor() / max_speed);
+                duration.max(shortest)
+            }
+            None => duration,
+        };
+
+        let next_move = Move { from, to: angle, duration, easing };
+        let shared = self.shared.clone();
+        let update_period = self.config.update_period;
+        self.motion = Some(Motion::spawn(move |cancelled| {
+            if let Err(error) = next_move.run(&shared.servo, update_period, cancelled) {
+                *shared.error.lock().unwrap() = Some(error);
+            }
+        }));
+        Ok(())
+    }
+
+    /// Block until the current move is done, with its error if it failed.
+    pub fn wait(&mut self) -> Result<()> {
+        if let Some(motion) = self.motion.take() {
+            motion.join();
+        }
+        match self.shared.error.lock().unwrap().take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the current move where it is.
+    pub fn cancel(&mut self) {
+        if let Some(motion) = self.motion.take() {
+            motion.cancel();
+        }
+    }
+
+    /// Whether a move is in progress.
+    pub fn is_moving(&self) -> bool {
+        self.motion.as_ref().is_some_and(|motion| !motion.is_finished())
+    }
+}
+
+impl<S: Servo + Send + 'static> Servo for SmoothServo<S> {
+    fn spec(&self) -> &ServoSpec {
+        &self.spec
+    }
+
+    fn calibration(&self) -> &ServoCalibration {
+        &self.calibration
+    }
+
+    fn current_angle(&self) -> f64 {
+        self.shared.servo.lock().unwrap().current_angle()
+    }
+
+    /// Move as fast as the speed limit allows and wait until it's there.
+    fn rotate_to_angle(&mut self, degrees: f64) -> Result<()> {
+        self.move_to(degrees, Duration::from_secs(0), Easing::Linear)?;
+        self.wait()
+    }
+}
+
+impl<S: Servo + Send + 'static> Drop for SmoothServo<S> {
+    /// Stop the current move, the servo stays where it is.
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// One move, from the thread's point of view.
+struct Move {
+    from: f64,
+    to: f64,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Move {
+    // Send the pulses until the move is done or cancelled.
+    fn run<S: Servo>(&self, servo: &Mutex<S>, update_period: Duration, cancelled: &AtomicBool) -> Result<()> {
+        let start = Instant::now();
+
+        while !cancelled.load(Ordering::SeqCst) {
+            let time = if self.duration.is_zero() { 1.0 } else { start.elapsed().as_secs_f64() / self.duration.as_secs_f64() };
+            let angle = self.from + (self.to - self.from) * self.easing.progress(time);
+
+            servo.lock().unwrap().rotate_to_angle(angle)?;
+
+            if time >= 1.0 {
+                break;
+            }
+            thread::sleep(update_period);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::servo::PwmServo;
+    use pwm_util::{ MockPwm, PwmOutput };
+    use rppal::pwm::Channel;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    fn servo(mock: &MockPwm, config: MotionConfig) -> SmoothServo<PwmServo<MockPwm>> {
+        let output = PwmOutput::with_backend(mock.clone(), Channel::Pwm0);
+        let servo = PwmServo::new(output, ServoSpec::SG90, ServoCalibration::default(), 0.0).unwrap();
+        SmoothServo::new(servo, config)
+    }
+
+    #[test]
+    fn easing_curves_should_start_at_0_and_end_at_1() {
+        for easing in [Easing::Linear, Easing::EaseInOut, Easing::Trapezoidal { ramp: 0.25 }, Easing::Trapezoidal { ramp: 0.5 }].iter() {
+            assert!(close(easing.progress(0.0), 0.0));
+            assert!(close(easing.progress(0.5), 0.5));
+            assert!(close(easing.progress(1.0), 1.0));
+        }
+        assert!(Easing::EaseInOut.progress(0.1) < Easing::Linear.progress(0.1));
+        assert!(matches!(Easing::Trapezoidal { ramp: 0.6 }.validate(), Err(ServoError::InvalidMotion(_))));
+    }
+
+    #[test]
+    fn move_should_step_smoothly_to_the_target() {
+        let mock = MockPwm::new();
+        let mut gate = servo(&mock, MotionConfig { max_degrees_per_second: None, update_period: ms(5) });
+        let setup_steps = mock.duty_history().len();
+
+        gate.move_to(90.0, ms(100), Easing::EaseInOut).unwrap();
+        gate.wait().unwrap();
+
+        let duties = mock.duty_history().split_off(setup_steps);
+        // From 1.5ms down to 0.5ms at 50Hz, one step at a time
+        assert!(duties.len() > 5);
+        assert!(duties.windows(2).all(|pair| pair[1] <= pair[0] + 1e-12));
+        assert!(close(*duties.last().unwrap(), 0.5 / 20.0));
+        assert!(close(gate.current_angle(), 90.0));
+        assert!(mock.is_enabled());
+    }
+
+    #[test]
+    fn speed_limit_and_cancel_should_hold_the_servo_back() {
+        let mock = MockPwm::new();
+        // 90° at 180°/s takes at least 500ms, whatever was asked
+        let mut gate = servo(&mock, MotionConfig { max_degrees_per_second: Some(180.0), update_period: ms(5) });
+
+        gate.move_to(90.0, ms(0), Easing::Linear).unwrap();
+        thread::sleep(ms(100));
+        assert!(gate.is_moving());
+        gate.cancel();
+
+        let angle = gate.current_angle();
+        assert!(angle > 0.0 && angle < 45.0);
+        assert!(!gate.is_moving());
+        assert!(gate.move_to(120.0, ms(100), Easing::Linear).is_err());
+    }
+
+    // Fails its first `failures` moves.
+    struct FlakyServo {
+        calibration: ServoCalibration,
+        angle: f64,
+        failures: u32,
+    }
+
+    impl Servo for FlakyServo {
+        fn spec(&self) -> &ServoSpec {
+            &ServoSpec::SG90
+        }
+
+        fn calibration(&self) -> &ServoCalibration {
+            &self.calibration
+        }
+
+        fn current_angle(&self) -> f64 {
+            self.angle
+        }
+
+        fn rotate_to_angle(&mut self, degrees: f64) -> Result<()> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(ServoError::InvalidMotion("flaky servo".to_string()));
+            }
+            self.angle = degrees;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn wait_should_only_report_the_last_move() {
+        let flaky = FlakyServo { calibration: ServoCalibration::default(), angle: 0.0, failures: 1 };
+        let mut gate = SmoothServo::new(flaky, MotionConfig { max_degrees_per_second: None, update_period: ms(5) });
+
+        // Fails in the background, nobody waits for it
+        gate.move_to(45.0, ms(0), Easing::Linear).unwrap();
+        thread::sleep(ms(50));
+
+        gate.move_to(30.0, ms(20), Easing::Linear).unwrap();
+        gate.wait().unwrap();
+        assert!(close(gate.current_angle(), 30.0));
+    }
+
+    #[test]
+    fn bad_speed_limits_should_be_rejected() {
+        for max_speed in [0.0, -90.0, f64::NAN].iter() {
+            let mut gate = servo(&MockPwm::new(), MotionConfig { max_degrees_per_second: Some(*max_speed), update_period: ms(5) });
+            assert!(matches!(gate.move_to(45.0, ms(100), Easing::Linear), Err(ServoError::InvalidMotion(_))));
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Servo calibrations saved to a TOML file, one table per servo name.
+//
+// ```toml
+// [gate]
+// min_pulse_ms = 0.58
+// center_pulse_ms = 1.47
+// max_pulse_ms = 2.31
+// travel_degrees = 90.0
+// reversed = false
+// min_angle = -90.0
+// max_angle = 60.0
+// ```
+//
+// ```rust
+// let mut profiles = CalibrationProfiles::load("servo_calibration.toml")?;
+// let gate = profiles.get_or_default("gate");
+//
+// profiles.insert("gate", calibrate_with_button(&button, &clock, set_pulse)?);
+// profiles.save("servo_calibration.toml")?;
+// ```
+//
+// Missing fields take the SG90 datasheet value.
+
+use std::{ collections::BTreeMap, fs, io, path::Path };
+use crate::{ calibration::ServoCalibration, error::{ Result, ServoError } };
+
+/// Calibrations keyed by servo name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CalibrationProfiles {
+    profiles: BTreeMap<String, ServoCalibration>,
+}
+
+impl CalibrationProfiles {
+    /// Read `path`, a file which doesn't exist yet gives no profiles.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => CalibrationProfiles::from_toml_str(&text).map_err(|error| match error {
+                ServoError::InvalidCalibration(message) => {
+                    ServoError::InvalidCalibration(format!("`{}`: {}", path.display(), message))
+                }
+                other => other,
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(CalibrationProfiles::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Parse and validate the profiles.
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        let profiles: BTreeMap<String, ServoCalibration> =
+            toml::from_str(text).map_err(|error| ServoError::InvalidCalibration(error.to_string()))?;
+
+        for (name, calibration) in profiles.iter() {
+            calibration.validate().map_err(|error| match error {
+                ServoError::InvalidCalibration(message) => ServoError::InvalidCalibration(format!("[{}] {}", name, message)),
+                other => other,
+            })?;
+        }
+
+        Ok(CalibrationProfiles { profiles })
+    }
+
+    /// Write all profiles to `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_toml_string()?)?;
+        Ok(())
+    }
+
+    /// The file content `save` writes.
+    pub fn to_toml_string(&self) -> Result<String> {
+        toml::to_string(&self.profiles)
+            .map_err(|error| ServoError::InvalidCalibration(format!("can't write the profiles as TOML: {}", error)))
+    }
+
+    /// Calibration of `name`, if there's one.
+    pub fn get(&self, name: &str) -> Option<ServoCalibration> {
+        self.profiles.get(name).copied()
+    }
+
+    /// Calibration of `name`, or the datasheet values.
+    pub fn get_or_default(&self, name: &str) -> ServoCalibration {
+        self.get(name).unwrap_or_default()
+    }
+
+    /// Add or replace the calibration of `name`.
+    pub fn insert(&mut self, name: &str, calibration: ServoCalibration) {
+        self.profiles.insert(name.to_string(), calibration);
+    }
+
+    /// Servo names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        self.profiles.keys().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn profiles_should_survive_a_round_trip() {
+        let mut profiles = CalibrationProfiles::default();
+        profiles.insert("gate", ServoCalibration { min_pulse_ms: 0.58, max_angle: 60.0, ..Default::default() });
+        profiles.insert("camera", ServoCalibration { reversed: true, ..Default::default() });
+
+        let reloaded = CalibrationProfiles::from_toml_str(&profiles.to_toml_string().unwrap()).unwrap();
+        assert_eq!(reloaded, profiles);
+        assert_eq!(reloaded.names(), vec!["camera", "gate"]);
+        assert_eq!(reloaded.get_or_default("unknown"), ServoCalibration::default());
+    }
+
+    #[test]
+    fn partial_or_bad_profiles_should_be_handled() {
+        let profiles = CalibrationProfiles::from_toml_str("[gate]\nmax_angle = 45.0\n").unwrap();
+        assert_eq!(profiles.get("gate"), Some(ServoCalibration { max_angle: 45.0, ..Default::default() }));
+
+        match CalibrationProfiles::from_toml_str("[gate]\ncenter_pulse_ms = 3.0\n") {
+            Err(ServoError::InvalidCalibration(message)) => assert!(message.starts_with("[gate]")),
+            other => panic!("expected `InvalidCalibration`, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,301 @@
+// Servo models.
+//
+// Hobby servos all take a pulse every period, its width sets the angle (or
+// the speed for continuous rotation ones). What differs between models is the
+// period, the pulse range and how far the horn turns for that range.
+// `ServoSpec` holds those numbers, and application code moves any model
+// through the `Servo` trait:
+//
+// ```rust
+// let output = PwmOutput::open(Channel::Pwm0, "arm servo")?;
+// let mut arm = PwmServo::new(output, ServoSpec::MG996R, ServoSpec::MG996R.calibration(), 0.0)?;
+// raise(&mut arm)?;
+//
+// fn raise<S: Servo>(servo: &mut S) -> Result<()> {
+//     servo.rotate_to_angle(servo.calibration().max_angle)
+// }
+// ```
+//
+// | Spec                         | Period | Pulses (`+max`/`0`/`-max`) | Travel  |
+// |------------------------------|--------|----------------------------|---------|
+// | `ServoSpec::SG90`            | 20ms   | 0.5 / 1.5 / 2.4ms          | ±90°    |
+// | `ServoSpec::MG90S`           | 20ms   | 0.5 / 1.5 / 2.5ms          | ±90°    |
+// | `ServoSpec::MG996R`          | 20ms   | 0.5 / 1.5 / 2.5ms          | ±90°    |
+// | `ServoSpec::SG90_CONTINUOUS` | 20ms   | 1.0 / 1.5 / 2.0ms          | speed   |
+
+use pwm_util::{ PwmBackend, PwmOutput };
+use rppal::pwm::Pwm;
+use crate::{
+    calibration::ServoCalibration, error::{ Result, ServoError }, SG90_MOTOR_INIT_POSITION_PULSE_MS, SG90_MOTOR_MAX_ANGLE,
+    SG90_MOTOR_NEGATIVE_90_DEGREE_PULSE_MS, SG90_MOTOR_PERIOD_MS, SG90_MOTOR_POSITIVE_90_DEGREE_PULSE_MS,
+};
+
+/// Datasheet numbers of one servo model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServoSpec {
+    /// Model name, for messages.
+    pub name: &'static str,
+    /// Time between two pulses, in ms.
+    pub period_ms: f64,
+    /// Shortest pulse, `+travel_degrees` (full speed one way for continuous servos), in ms.
+    pub min_pulse_ms: f64,
+    /// Pulse for `0` (stop for continuous servos), in ms.
+    pub center_pulse_ms: f64,
+    /// Longest pulse, `-travel_degrees` (full speed the other way), in ms.
+    pub max_pulse_ms: f64,
+    /// How far the horn turns each side of the center, in degrees.
+    pub travel_degrees: f64,
+    /// Pulses set the speed instead of the angle.
+    pub continuous: bool,
+}
+
+impl ServoSpec {
+    /// TowerPro SG90 micro servo.
+    pub const SG90: ServoSpec = ServoSpec {
+        name: "SG90",
+        period_ms: SG90_MOTOR_PERIOD_MS,
+        min_pulse_ms: SG90_MOTOR_POSITIVE_90_DEGREE_PULSE_MS,
+        center_pulse_ms: SG90_MOTOR_INIT_POSITION_PULSE_MS,
+        max_pulse_ms: SG90_MOTOR_NEGATIVE_90_DEGREE_PULSE_MS,
+        travel_degrees: SG90_MOTOR_MAX_ANGLE,
+        continuous: false,
+    };
+
+    /// TowerPro MG90S, the metal gear SG90.
+    pub const MG90S: ServoSpec = ServoSpec {
+        name: "MG90S",
+        period_ms: 20.0,
+        min_pulse_ms: 0.5,
+        center_pulse_ms: 1.5,
+        max_pulse_ms: 2.5,
+        travel_degrees: 90.0,
+        continuous: false,
+    };
+
+    /// TowerPro MG996R, standard size high torque servo.
+    pub const MG996R: ServoSpec = ServoSpec {
+        name: "MG996R",
+        period_ms: 20.0,
+        min_pulse_ms: 0.5,
+        center_pulse_ms: 1.5,
+        max_pulse_ms: 2.5,
+        travel_degrees: 90.0,
+        continuous: false,
+    };
+
+    /// 360° (continuous rotation) SG90 variants, e.g. FS90R.
+    pub const SG90_CONTINUOUS: ServoSpec = ServoSpec {
+        name: "SG90 continuous",
+        period_ms: 20.0,
+        min_pulse_ms: 1.0,
+        center_pulse_ms: 1.5,
+        max_pulse_ms: 2.0,
+        travel_degrees: 90.0,
+        continuous: true,
+    };
+
+    /// PWM frequency, in Hz.
+    pub fn frequency(&self) -> f64 {
+        1000.0 / self.period_ms
+    }
+
+    /// Datasheet calibration, the full travel is allowed.
+    pub fn calibration(&self) -> ServoCalibration {
+        ServoCalibration {
+            min_pulse_ms: self.min_pulse_ms,
+            center_pulse_ms: self.center_pulse_ms,
+            max_pulse_ms: self.max_pulse_ms,
+            travel_degrees: self.travel_degrees,
+            reversed: false,
+            min_angle: -self.travel_degrees,
+            max_angle: self.travel_degrees,
+        }
+    }
+}
+
+/// A positional servo, whatever the model.
+pub trait Servo {
+    /// Model of the servo.
+    fn spec(&self) -> &ServoSpec;
+
+    /// Pulses and limits in use.
+    fn calibration(&self) -> &ServoCalibration;
+
+    /// Angle of the last pulse sent, in degrees.
+    fn current_angle(&self) -> f64;
+
+    /// Go to `degrees`, within the calibration limits.
+    fn rotate_to_angle(&mut self, degrees: f64) -> Result<()>;
+
+    /// Go to `0`.
+    fn rotate_to_center(&mut self) -> Result<()> {
+        self.rotate_to_angle(0.0)
+    }
+}
+
+/// Positional servo of any `ServoSpec` on a PWM channel.
+pub struct PwmServo<B: PwmBackend = Pwm> {
+    output: PwmOutput<B>,
+    spec: ServoSpec,
+    calibration: ServoCalibration,
+    angle: f64,
+}
+
+impl<B: PwmBackend> PwmServo<B> {
+    /// Take over `output`, set it up for `spec` and go to `start_angle` right away.
+    pub fn new(mut output: PwmOutput<B>, spec: ServoSpec, calibration: ServoCalibration, start_angle: f64) -> Result<Self> {
+        if spec.continuous {
+            return Err(ServoError::InvalidCalibration(format!("{} is a continuous rotation servo, it has no angle", spec.name)));
+        }
+        calibration.validate()?;
+        let pulse_width_ms = calibration.pulse_width_ms(start_angle)?;
+
+        output.set_frequency(spec.frequency())?;
+        output.set_pulse_width_us(pulse_width_ms * 1000.0)?;
+        output.enable()?;
+
+        Ok(PwmServo { output, spec, calibration, angle: start_angle })
+    }
+
+    /// The PWM output, e.g. to check a `MockPwm`.
+    pub fn output(&self) -> &PwmOutput<B> {
+        &self.output
+    }
+
+    /// Use `calibration` from now on. The pulse is resent for the current
+    /// angle, clamped to the new limits, so the servo never sits outside them.
+    pub fn set_calibration(&mut self, calibration: ServoCalibration) -> Result<()> {
+        calibration.validate()?;
+        let angle = self.angle.max(calibration.min_angle).min(calibration.max_angle);
+        let pulse_width_ms = calibration.pulse_width_ms(angle)?;
+
+        self.output.set_pulse_width_us(pulse_width_ms * 1000.0)?;
+        self.calibration = calibration;
+        self.angle = angle;
+        Ok(())
+    }
+
+    pub(crate) fn output_mut(&mut self) -> &mut PwmOutput<B> {
+        &mut self.output
+    }
+
+    /// Stop the pulses, the servo goes limp (no holding torque) until the next move.
+    pub fn detach(&mut self) -> Result<()> {
+        self.output.disable()?;
+        Ok(())
+    }
+
+    /// Whether the servo gets pulses (and holds its position).
+    pub fn is_attached(&self) -> bool {
+        self.output.is_enabled()
+    }
+}
+
+impl<B: PwmBackend> Servo for PwmServo<B> {
+    fn spec(&self) -> &ServoSpec {
+        &self.spec
+    }
+
+    fn calibration(&self) -> &ServoCalibration {
+        &self.calibration
+    }
+
+    fn current_angle(&self) -> f64 {
+        self.angle
+    }
+
+    fn rotate_to_angle(&mut self, degrees: f64) -> Result<()> {
+        let pulse_width_ms = self.calibration.pulse_width_ms(degrees)?;
+        self.output.set_pulse_width_us(pulse_width_ms * 1000.0)?;
+        self.angle = degrees;
+        if !self.output.is_enabled() {
+            self.output.enable()?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: PwmBackend> Drop for PwmServo<B> {
+    /// Stop the pulses, so the servo doesn't keep holding torque.
+    fn drop(&mut self) {
+        let _ = self.output.disable();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use pwm_util::MockPwm;
+    use rppal::pwm::Channel;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    // Works for every model, that's the point
+    fn open_fully<S: Servo>(servo: &mut S) -> Result<()> {
+        let max_angle = servo.calibration().max_angle;
+        servo.rotate_to_angle(max_angle)
+    }
+
+    #[test]
+    fn specs_should_give_their_datasheet_calibration() {
+        for spec in [ServoSpec::SG90, ServoSpec::MG90S, ServoSpec::MG996R].iter() {
+            let calibration = spec.calibration();
+            calibration.validate().unwrap();
+            assert!(close(calibration.pulse_width_ms(spec.travel_degrees).unwrap(), spec.min_pulse_ms));
+            assert!(close(calibration.pulse_width_ms(0.0).unwrap(), spec.center_pulse_ms));
+            assert!(close(spec.frequency(), 50.0));
+        }
+        assert_eq!(ServoSpec::SG90.calibration(), ServoCalibration::default());
+    }
+
+    #[test]
+    fn pwm_servo_should_drive_any_positional_model() {
+        let mock = MockPwm::new();
+        let output = PwmOutput::with_backend(mock.clone(), Channel::Pwm0);
+        let mut servo = PwmServo::new(output, ServoSpec::MG996R, ServoSpec::MG996R.calibration(), 0.0).unwrap();
+        assert!(close(mock.duty_cycle(), 1.5 / 20.0) && mock.is_enabled());
+
+        servo.rotate_to_angle(-90.0).unwrap();
+        assert!(close(mock.duty_cycle(), 2.5 / 20.0));
+        open_fully(&mut servo).unwrap();
+        assert!(close(servo.current_angle(), 90.0));
+        assert!(close(servo.output().pulse_width_us(), 500.0));
+
+        assert!(servo.rotate_to_angle(100.0).is_err());
+        assert!(close(servo.current_angle(), 90.0));
+    }
+
+    #[test]
+    fn narrower_calibrations_should_pull_the_servo_back_within_the_limits() {
+        let mock = MockPwm::new();
+        let output = PwmOutput::with_backend(mock.clone(), Channel::Pwm0);
+        let mut servo = PwmServo::new(output, ServoSpec::MG90S, ServoSpec::MG90S.calibration(), 80.0).unwrap();
+
+        let narrow = ServoCalibration { min_angle: -45.0, max_angle: 45.0, ..ServoSpec::MG90S.calibration() };
+        servo.set_calibration(narrow).unwrap();
+        assert!(close(servo.current_angle(), 45.0));
+        assert!(close(mock.duty_cycle(), 1.0 / 20.0));
+
+        // Same angle, new pulses
+        servo.set_calibration(ServoCalibration { min_pulse_ms: 0.7, ..narrow }).unwrap();
+        assert!(close(servo.current_angle(), 45.0));
+        assert!(close(mock.duty_cycle(), 1.1 / 20.0));
+
+        assert!(servo.set_calibration(ServoCalibration { max_angle: 120.0, ..narrow }).is_err());
+        assert!(close(servo.current_angle(), 45.0));
+    }
+
+    #[test]
+    fn continuous_specs_should_be_refused() {
+        let output = PwmOutput::with_backend(MockPwm::new(), Channel::Pwm1);
+        let spec = ServoSpec::SG90_CONTINUOUS;
+        match PwmServo::new(output, spec, spec.calibration(), 0.0) {
+            Err(ServoError::InvalidCalibration(message)) => assert!(message.contains("continuous")),
+            Err(other) => panic!("expected `InvalidCalibration`, got {:?}", other),
+            Ok(_) => panic!("expected `InvalidCalibration`"),
+        }
+    }
+}
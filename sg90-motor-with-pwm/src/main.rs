@@ -1,62 +1,89 @@
-/// Use button to control laser
-///
-/// 1. First, you need to enable the PWM functionality by add the setting below to `/boot/firmware/usercfg.txt` and reboot:
-/// dtoverlay=pwm-2chan
-///
-/// 2. After reboot, you will be able to see `/sys/class/pwm/pwmchip0/` folder there. Otherwise,
-///    you will get `NotFound` error when u call `Pwm::with_frequency()`.
-///
-///
-/// How to connect the circuit: 
-///
-/// Pi               Button   SG90 Motor
-/// ====             ======   =============
-/// 5V     ----------  VCC 
-/// GND    ----------  GND 
-/// GPIO17 ----------  SIG
-///
-/// GPIO18 -----------------  Pwm(Orange)
-/// 5V     -----------------  VCC(Red) 
-/// GND    -----------------  GND(Brown) 
-///
-///
-/// You need to run by `sudo` if you see the error below:
-///
-/// `Error: PermissionDenied("/dev/gpiomem")`
-///
-
-use rppal::{
-    gpio::{Result}
+//! Use button to control the SG90 servo motor, the gate opens and closes smoothly (`SmoothServo`)
+//!
+//! 1. First, you need to enable the PWM functionality by add the setting below to `/boot/firmware/usercfg.txt` and reboot:
+//!    `dtoverlay=pwm-2chan`
+//!
+//! 2. After reboot, you will be able to see `/sys/class/pwm/pwmchip0/` folder there. Otherwise,
+//!    you will get the `GpioError::PwmChipMissing` error (wrapped in `ServoError::Gpio`).
+//!
+//! 3. `cargo run -- --calibrate` finds the pulses of your servo with the button (see the
+//!    instructions it prints) and saves them to `servo_calibration.toml`, which is loaded on
+//!    every start. Without that file the datasheet values are used.
+//!
+//!
+//! How to connect the circuit: 
+//!
+//! Pi               Button   SG90 Motor
+//! ====             ======   =============
+//! 5V     ----------  VCC 
+//! GND    ----------  GND 
+//! GPIO17 ----------  SIG
+//!
+//! GPIO18 -----------------  Pwm(Orange)
+//! 5V     -----------------  VCC(Red) 
+//! GND    -----------------  GND(Brown) 
+//!
+//!
+//! You need to run by `sudo` if you see the error below:
+//!
+//! ```text
+//! Error: permission denied on `/dev/gpiomem`, run with `sudo`, ...
+//! ```
+
+use std::time::Duration;
+use gpio_util::{GpioUtil, SystemClock};
+use pwm_util::PwmOutput;
+use rppal::pwm::Channel;
+use servo_motor_util::{
+    calibrate_with_button, CalibrationProfiles, Easing, MotionConfig, PwmServo, Result, Servo, ServoSpec, SmoothServo,
 };
-use gpio_util::{GpioUtil};
-use servo_motor_util::{SG90ServoMotor, SG90ServoMotorInitPosition};
 
 const GPIO_BUTTON_SIGNAL_PIN: u8 = 17;
+const CALIBRATION_FILE: &str = "servo_calibration.toml";
+const SERVO_NAME: &str = "gate";
+// Swap for `ServoSpec::MG90S` etc., nothing else changes
+const SERVO_SPEC: ServoSpec = ServoSpec::SG90;
+// Slow enough not to slam the gate or brown out the 5V rail
+const GATE_MOVE_DURATION: Duration = Duration::from_millis(1500);
 
 
-///
+/// Entry point, every button press opens or closes the gate.
 fn main() -> Result<()> {
-    let mut sg90_motor = SG90ServoMotor::get_motor_with_init_position(SG90ServoMotorInitPosition::Init, false);
-    match sg90_motor  {
-        Ok(ref pwm_value) => {
-            println!("SG90 motor set to init position, {:?}", pwm_value);
-        }
-        // Exit if open PWM fail.
-        Err(error) => {
-            println!("SG90 motor init fail: {:?}", error);
-            return Ok(());
-        }
-    }
+    // Exit if open PWM fail, e.g. `/sys/class/pwm/pwmchip0` doesn't exist.
+    let mut sg90_output = PwmOutput::open(Channel::Pwm0, "gate servo")?;
+    sg90_output.set_frequency(SERVO_SPEC.frequency())?;
+    sg90_output.set_pulse_width_us(SERVO_SPEC.center_pulse_ms * 1000.0)?;
+    sg90_output.enable()?;
+    println!("SG90 motor set to init position");
+
+    let button_signal = GpioUtil::create_input_pin(GPIO_BUTTON_SIGNAL_PIN)?;
 
-    let button_signal = GpioUtil::create_input_pin(GPIO_BUTTON_SIGNAL_PIN).unwrap();
+    let mut profiles = CalibrationProfiles::load(CALIBRATION_FILE)?;
+    if std::env::args().any(|arg| arg == "--calibrate") {
+        println!("Calibrating `{}`, the servo sweeps slowly, press the button:", SERVO_NAME);
+        println!("1. when the horn lines up with the center mark,");
+        println!("2. as soon as it stops moving (or buzzes) at the `+90` end,");
+        println!("3. the same at the `-90` end.");
+
+        let calibration = calibrate_with_button(&button_signal, &SystemClock::new(), |pulse_width_ms| {
+            sg90_output.set_pulse_width_us(pulse_width_ms * 1000.0)
+        })?;
+        profiles.insert(SERVO_NAME, calibration);
+        profiles.save(CALIBRATION_FILE)?;
+        println!("Saved to `{}`: {:?}", CALIBRATION_FILE, calibration);
+    }
+    let calibration = profiles.get(SERVO_NAME).unwrap_or_else(|| SERVO_SPEC.calibration());
+    let servo = PwmServo::new(sg90_output, SERVO_SPEC, calibration, 0.0)?;
+    let mut gate = SmoothServo::new(servo, MotionConfig::default());
 
     let mut open_gate = false;
 
     loop {
         GpioUtil::block_until_button_pressed(&button_signal);
 
-        if !open_gate { let _  = SG90ServoMotor::rotate_to_positive_position(&mut sg90_motor); }
-        else { let _  = SG90ServoMotor::rotate_to_init_position(&mut sg90_motor); }
+        // A press while the gate is still moving turns it around
+        let angle = if !open_gate { gate.calibration().max_angle } else { 0.0 };
+        gate.move_to(angle, GATE_MOVE_DURATION, Easing::EaseInOut)?;
 
         open_gate = !open_gate;
     }